@@ -0,0 +1,51 @@
+//! Compare image preparation and upload times, showing uploads of prepared
+//! images are dominated by the USB transfer
+//!
+//! Requires an attached device, run with:
+//! `cargo run --release --example prepare_bench -- [PID in hex] [iterations]`
+
+use std::time::Instant;
+
+use streamdeck::{ImageOptions, StreamDeck};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+
+    let pid = args
+        .next()
+        .map(|p| u16::from_str_radix(&p, 16).expect("invalid pid"))
+        .unwrap_or(streamdeck::pids::XL);
+    let iterations: u32 = args
+        .next()
+        .map(|n| n.parse().expect("invalid iteration count"))
+        .unwrap_or(20);
+
+    let mut deck = StreamDeck::connect(0x0fd9, pid, None).expect("error connecting to device");
+    let image = image::open("./icons/power.png").expect("error loading image");
+    let opts = ImageOptions::default();
+
+    let start = Instant::now();
+    let mut prepared = Vec::new();
+    for _ in 0..iterations {
+        prepared.push(
+            deck.prepare_image(&image, &opts)
+                .expect("error preparing image"),
+        );
+    }
+    let prepare = start.elapsed();
+
+    let start = Instant::now();
+    for p in &prepared {
+        deck.write_button_image(0, p)
+            .expect("error uploading image");
+    }
+    let upload = start.elapsed();
+
+    println!(
+        "prepare: {:?} per image, upload: {:?} per image",
+        prepare / iterations,
+        upload / iterations
+    );
+
+    deck.reset().expect("error resetting device");
+}
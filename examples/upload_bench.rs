@@ -0,0 +1,48 @@
+//! Compare image upload times at a range of upload chunk sizes
+//!
+//! Requires an attached device, run with:
+//! `cargo run --release --example upload_bench -- [PID in hex] [iterations]`
+
+use std::time::Instant;
+
+use streamdeck::{Colour, StreamDeck};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+
+    let pid = args
+        .next()
+        .map(|p| u16::from_str_radix(&p, 16).expect("invalid pid"))
+        .unwrap_or(streamdeck::pids::XL);
+    let iterations: u32 = args
+        .next()
+        .map(|n| n.parse().expect("invalid iteration count"))
+        .unwrap_or(20);
+
+    let mut deck = StreamDeck::connect(0x0fd9, pid, None).expect("error connecting to device");
+    let max = deck.kind().max_upload_chunk_size();
+
+    for size in [max / 4, max / 2, max] {
+        if let Err(e) = deck.set_upload_chunk_size(size) {
+            println!("chunk size {:5}: {:?}", size, e);
+            continue;
+        }
+
+        let start = Instant::now();
+        for i in 0..iterations {
+            let colour = Colour::from_hsv(i as f32 * 360.0 / iterations as f32, 1.0, 1.0);
+            deck.set_button_rgb(0, &colour)
+                .expect("error uploading image");
+        }
+        let elapsed = start.elapsed();
+
+        println!(
+            "chunk size {:5}: {:?} total, {:?} per upload",
+            size,
+            elapsed,
+            elapsed / iterations
+        );
+    }
+
+    deck.reset().expect("error resetting device");
+}
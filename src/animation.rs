@@ -0,0 +1,538 @@
+//! Animation helpers
+//!
+//! These are caller driven, with no internal threads or timers, so they can be
+//! stepped from any event loop.
+
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+
+use crate::{Colour, DeviceImage, Error, StreamDeck};
+
+/// Looping sequence of pre-encoded frames for a set of keys
+///
+/// Frames are encoded once when added, so each [FrameSequence::advance] only has to
+/// upload the next frame for each key. Keys may have differing numbers of frames,
+/// each key loops through its own frames.
+#[derive(Default)]
+pub struct FrameSequence {
+    keys: Vec<(u8, Vec<DeviceImage>)>,
+    position: usize,
+}
+
+impl FrameSequence {
+    /// Create a new empty frame sequence
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encode and add frames for a key, replacing any existing frames for the key
+    ///
+    /// Frames must match the device image size.
+    pub fn add_key(
+        &mut self,
+        deck: &StreamDeck,
+        key: u8,
+        frames: Vec<DynamicImage>,
+    ) -> Result<(), Error> {
+        let frames = frames
+            .into_iter()
+            .map(|f| deck.encode_image(f))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.add_key_encoded(deck, key, frames)
+    }
+
+    /// Add already encoded frames for a key, replacing any existing frames for the key
+    pub fn add_key_encoded(
+        &mut self,
+        deck: &StreamDeck,
+        key: u8,
+        frames: Vec<DeviceImage>,
+    ) -> Result<(), Error> {
+        deck.kind().translate_key_index(key)?;
+
+        self.keys.retain(|(k, _)| *k != key);
+        if !frames.is_empty() {
+            self.keys.push((key, frames));
+        }
+
+        Ok(())
+    }
+
+    /// Upload the next frame for each key as a single frame
+    ///
+    /// This uses [StreamDeck::frame], so also flushes any writes already buffered on the device.
+    pub fn advance(&mut self, deck: &mut StreamDeck) -> Result<(), Error> {
+        let position = self.position;
+
+        deck.frame(|deck| {
+            for (key, frames) in &self.keys {
+                deck.write_button_image(*key, &frames[position % frames.len()])?;
+            }
+            Ok(())
+        })?;
+
+        self.position = self.position.wrapping_add(1);
+
+        Ok(())
+    }
+
+    /// Fetch the number of frames advanced since creation or the last reset
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Restart the sequence from the first frame
+    pub fn reset(&mut self) {
+        self.position = 0;
+    }
+}
+
+/// Easing functions for tweens, mapping linear progress to eased progress
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Easing {
+    Linear,
+    /// Quadratic ease in and out, slow at either end of the tween
+    EaseInOut,
+}
+
+impl Easing {
+    /// Apply the easing function to progress `t` (0.0 to 1.0)
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut if t < 0.5 => 2.0 * t * t,
+            Easing::EaseInOut => 1.0 - (-2.0 * t + 2.0).powi(2) / 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TweenValue {
+    Brightness(u8),
+    Colour(u8, Colour),
+}
+
+impl TweenValue {
+    fn target(&self) -> Option<u8> {
+        match self {
+            TweenValue::Brightness(_) => None,
+            TweenValue::Colour(key, _) => Some(*key),
+        }
+    }
+}
+
+struct Tween {
+    from: TweenValue,
+    to: TweenValue,
+    duration: Duration,
+    easing: Easing,
+    start: Option<Instant>,
+    last: Option<TweenValue>,
+}
+
+impl Tween {
+    /// Compute the value at the provided time, starting the tween if required
+    fn value(&mut self, now: Instant) -> (TweenValue, bool) {
+        let start = *self.start.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(start);
+
+        if elapsed >= self.duration {
+            return (self.to.clone(), true);
+        }
+
+        let t = self
+            .easing
+            .apply(elapsed.as_secs_f32() / self.duration.as_secs_f32());
+        let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+        let v = match (&self.from, &self.to) {
+            (TweenValue::Brightness(a), TweenValue::Brightness(b)) => {
+                TweenValue::Brightness(lerp(*a, *b))
+            }
+            (TweenValue::Colour(key, a), TweenValue::Colour(_, b)) => TweenValue::Colour(
+                *key,
+                Colour {
+                    r: lerp(a.r, b.r),
+                    g: lerp(a.g, b.g),
+                    b: lerp(a.b, b.b),
+                },
+            ),
+            _ => unreachable!(),
+        };
+
+        (v, false)
+    }
+}
+
+/// Steps brightness and key colour tweens
+///
+/// Tweens start on the first [Animator::tick] after they are added, and each tick
+/// only writes values that have changed since the previous tick. Adding a tween
+/// replaces any existing tween for the same brightness or key.
+#[derive(Default)]
+pub struct Animator {
+    tweens: Vec<Tween>,
+}
+
+impl Animator {
+    /// Create a new animator with no tweens
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fade the display brightness (in percent) between the provided values
+    pub fn fade_brightness(&mut self, from: u8, to: u8, duration: Duration, easing: Easing) {
+        self.add(
+            TweenValue::Brightness(from.min(100)),
+            TweenValue::Brightness(to.min(100)),
+            duration,
+            easing,
+        );
+    }
+
+    /// Fade a key between the provided colours
+    pub fn fade_colour(
+        &mut self,
+        deck: &StreamDeck,
+        key: u8,
+        from: Colour,
+        to: Colour,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), Error> {
+        deck.kind().translate_key_index(key)?;
+
+        self.add(
+            TweenValue::Colour(key, from),
+            TweenValue::Colour(key, to),
+            duration,
+            easing,
+        );
+
+        Ok(())
+    }
+
+    /// Fade every key between the provided colours in lockstep
+    ///
+    /// This replaces any existing colour tweens, with all keys written in a single frame each tick.
+    pub fn animate_all_colours(
+        &mut self,
+        deck: &StreamDeck,
+        from: Colour,
+        to: Colour,
+        duration: Duration,
+        easing: Easing,
+    ) {
+        for key in 0..deck.kind().keys() {
+            self.add(
+                TweenValue::Colour(key, from.clone()),
+                TweenValue::Colour(key, to.clone()),
+                duration,
+                easing,
+            );
+        }
+    }
+
+    fn add(&mut self, from: TweenValue, to: TweenValue, duration: Duration, easing: Easing) {
+        self.tweens.retain(|t| t.to.target() != to.target());
+        self.tweens.push(Tween {
+            from,
+            to,
+            duration,
+            easing,
+            start: None,
+            last: None,
+        });
+    }
+
+    /// Check whether any tweens are still running
+    pub fn is_active(&self) -> bool {
+        !self.tweens.is_empty()
+    }
+
+    /// Remove all tweens, leaving the device in its current state
+    pub fn clear(&mut self) {
+        self.tweens.clear();
+    }
+
+    /// Write the current value of each tween to the device, removing completed tweens
+    ///
+    /// Key colours are written as a single frame, see [StreamDeck::frame].
+    pub fn tick(&mut self, deck: &mut StreamDeck, now: Instant) -> Result<(), Error> {
+        let values: Vec<_> = self.tweens.iter_mut().map(|t| t.value(now)).collect();
+        let updates: Vec<_> = self
+            .tweens
+            .iter()
+            .zip(&values)
+            .filter(|(t, (v, _))| t.last.as_ref() != Some(v))
+            .map(|(_, (v, _))| v.clone())
+            .collect();
+
+        deck.frame(|deck| {
+            for value in updates {
+                match value {
+                    TweenValue::Brightness(b) => deck.set_brightness(b)?,
+                    TweenValue::Colour(key, c) => deck.set_button_rgb(key, &c)?,
+                }
+            }
+            Ok(())
+        })?;
+
+        // Values are only recorded once written, so failed writes are retried on the next tick
+        let mut done = Vec::with_capacity(values.len());
+        for (tween, (value, d)) in self.tweens.iter_mut().zip(values) {
+            tween.last = Some(value);
+            done.push(d);
+        }
+        let mut done = done.into_iter();
+        self.tweens.retain(|_| !done.next().unwrap_or(false));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ImageMode, Kind, MockTransport};
+    use image::{ImageBuffer, Rgb};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    fn solid(kind: Kind, c: [u8; 3]) -> DynamicImage {
+        let (w, h) = kind.image_size();
+        DynamicImage::ImageRgb8(ImageBuffer::from_pixel(w as u32, h as u32, Rgb(c)))
+    }
+
+    #[test]
+    fn frame_sequence_loops() {
+        let kind = Kind::Mini;
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), kind);
+
+        let (red, green, blue) = ([255, 0, 0], [0, 255, 0], [0, 0, 255]);
+
+        let mut seq = FrameSequence::new();
+        seq.add_key(&deck, 0, vec![solid(kind, red), solid(kind, green)])
+            .unwrap();
+        seq.add_key(
+            &deck,
+            1,
+            vec![solid(kind, red), solid(kind, green), solid(kind, blue)],
+        )
+        .unwrap();
+
+        // First pixel of each key upload in order (BGR on the mini)
+        let pixel_offset = kind.image_report_header_len() + kind.image_base().len();
+        let mut uploaded = Vec::new();
+        for _ in 0..3 {
+            mock.clear();
+            seq.advance(&mut deck).unwrap();
+
+            let frames: Vec<(u8, [u8; 3])> = mock
+                .written_packets()
+                .iter()
+                .filter(|p| p[2..4] == [0, 0])
+                .map(|p| {
+                    let mut rgb = [0u8; 3];
+                    rgb.copy_from_slice(&p[pixel_offset..pixel_offset + 3]);
+                    rgb.reverse();
+                    (p[5], rgb)
+                })
+                .collect();
+            uploaded.push(frames);
+        }
+
+        assert_eq!(uploaded[0], vec![(0, red), (1, red)]);
+        assert_eq!(uploaded[1], vec![(0, green), (1, green)]);
+        assert_eq!(uploaded[2], vec![(0, red), (1, blue)]);
+        assert_eq!(seq.position(), 3);
+    }
+
+    #[test]
+    fn easing() {
+        for e in [Easing::Linear, Easing::EaseInOut].iter() {
+            assert_eq!(e.apply(0.0), 0.0);
+            assert_eq!(e.apply(0.5), 0.5);
+            assert_eq!(e.apply(1.0), 1.0);
+            assert_eq!(e.apply(2.0), 1.0);
+        }
+        assert!(Easing::EaseInOut.apply(0.25) < 0.25);
+        assert!(Easing::EaseInOut.apply(0.75) > 0.75);
+    }
+
+    #[test]
+    fn animator_all_colours() {
+        let kind = Kind::Mini;
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), kind);
+
+        let mut animator = Animator::new();
+        animator.animate_all_colours(
+            &deck,
+            Colour::BLACK,
+            Colour::RED,
+            Duration::from_secs(1),
+            Easing::Linear,
+        );
+
+        // Key and red channel (BGR) of each key upload
+        let pixel_offset = kind.image_report_header_len() + kind.image_base().len() + 2;
+        let mut step = |now: Instant| {
+            mock.clear();
+            animator.tick(&mut deck, now).unwrap();
+            mock.written_packets()
+                .iter()
+                .filter(|p| p[2..4] == [0, 0])
+                .map(|p| (p[5], p[pixel_offset]))
+                .collect::<Vec<_>>()
+        };
+
+        let t0 = Instant::now();
+        assert_eq!(step(t0), (0..6).map(|k| (k, 0)).collect::<Vec<_>>());
+        assert_eq!(
+            step(t0 + Duration::from_millis(500)),
+            (0..6).map(|k| (k, 128)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            step(t0 + Duration::from_secs(1)),
+            (0..6).map(|k| (k, 255)).collect::<Vec<_>>()
+        );
+        assert!(!animator.is_active());
+    }
+
+    #[test]
+    fn animator_colour_fade() {
+        let kind = Kind::Mini;
+        assert_eq!(kind.image_mode(), ImageMode::Bmp);
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), kind);
+
+        let mut animator = Animator::new();
+        animator
+            .fade_colour(
+                &deck,
+                2,
+                Colour::BLACK,
+                Colour::WHITE,
+                Duration::from_secs(1),
+                Easing::Linear,
+            )
+            .unwrap();
+        animator.fade_brightness(0, 100, Duration::from_secs(2), Easing::EaseInOut);
+        assert!(animator
+            .fade_colour(
+                &deck,
+                6,
+                Colour::BLACK,
+                Colour::WHITE,
+                Duration::from_secs(1),
+                Easing::Linear
+            )
+            .is_err());
+
+        // First pixel of each key upload
+        let pixel_offset = kind.image_report_header_len() + kind.image_base().len();
+        let mut step = |now: Instant| {
+            mock.clear();
+            animator.tick(&mut deck, now).unwrap();
+            let pixels: Vec<u8> = mock
+                .written_packets()
+                .iter()
+                .filter(|p| p[2..4] == [0, 0])
+                .map(|p| p[pixel_offset])
+                .collect();
+            let brightness: Vec<u8> = mock.sent_feature_reports().iter().map(|r| r[5]).collect();
+            (pixels, brightness)
+        };
+
+        let t0 = Instant::now();
+        assert_eq!(step(t0), (vec![0], vec![0]));
+        assert_eq!(step(t0 + Duration::from_millis(500)), (vec![128], vec![13]));
+        // Unchanged values are not rewritten
+        assert_eq!(step(t0 + Duration::from_millis(500)), (vec![], vec![]));
+        assert_eq!(step(t0 + Duration::from_secs(1)), (vec![255], vec![50]));
+        assert_eq!(step(t0 + Duration::from_secs(3)), (vec![], vec![100]));
+        assert!(!animator.is_active());
+
+        assert_eq!(
+            deck.export_layout().keys[2],
+            Some(crate::KeyContent::Colour(Colour::WHITE))
+        );
+    }
+
+    /// Transport failing writes until enabled
+    struct FailingWrites {
+        inner: MockTransport,
+        fail: Arc<AtomicBool>,
+    }
+
+    impl crate::DeckTransport for FailingWrites {
+        fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+            match self.fail.load(Ordering::Relaxed) {
+                true => Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe).into()),
+                false => self.inner.write(data),
+            }
+        }
+
+        fn read(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> Result<usize, Error> {
+            self.inner.read(buf, timeout)
+        }
+
+        fn send_feature_report(&mut self, data: &[u8]) -> Result<(), Error> {
+            self.inner.send_feature_report(data)
+        }
+
+        fn get_feature_report(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.inner.get_feature_report(buf)
+        }
+
+        fn set_blocking_mode(&mut self, blocking: bool) -> Result<(), Error> {
+            self.inner.set_blocking_mode(blocking)
+        }
+    }
+
+    #[test]
+    fn animator_failed_write() {
+        let kind = Kind::Mini;
+        let (mock, fail) = (MockTransport::new(), Arc::new(AtomicBool::new(true)));
+        let transport = FailingWrites {
+            inner: mock.clone(),
+            fail: fail.clone(),
+        };
+        let mut deck = StreamDeck::with_transport(transport, kind);
+
+        let mut animator = Animator::new();
+        animator
+            .fade_colour(
+                &deck,
+                2,
+                Colour::BLACK,
+                Colour::WHITE,
+                Duration::ZERO,
+                Easing::Linear,
+            )
+            .unwrap();
+
+        // The finished tween is kept until its final value has been written
+        let now = Instant::now();
+        assert!(animator.tick(&mut deck, now).is_err());
+        assert!(animator.is_active());
+
+        fail.store(false, Ordering::Relaxed);
+        animator.tick(&mut deck, now).unwrap();
+        assert!(!animator.is_active());
+
+        let pixel_offset = kind.image_report_header_len() + kind.image_base().len();
+        let pixels: Vec<u8> = mock
+            .written_packets()
+            .iter()
+            .filter(|p| p[2..4] == [0, 0])
+            .map(|p| p[pixel_offset])
+            .collect();
+        assert_eq!(pixels, vec![255]);
+    }
+}
@@ -0,0 +1,225 @@
+//! Async device wrapper, for use from async runtimes without blocking the executor
+//!
+//! Calls are run in order on a worker thread owning the device, with results returned
+//! through futures that do not depend on a specific runtime. As with [SharedStreamDeck]
+//! reads should use a short timeout, as other calls wait for a read to complete.
+//!
+//! This requires the `async` feature.
+//!
+//! [SharedStreamDeck]: crate::SharedStreamDeck
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use image::DynamicImage;
+
+use crate::{ButtonStates, Colour, Error, InputReport, Kind, StreamDeck};
+
+type Job = Box<dyn FnOnce(&mut StreamDeck) + Send>;
+
+/// Async handle to a [StreamDeck]
+///
+/// The device is closed (see [StreamDeck::close]) on the worker thread once the handle
+/// is dropped and any calls in progress have completed.
+pub struct AsyncStreamDeck {
+    kind: Kind,
+    jobs: Sender<Job>,
+}
+
+impl AsyncStreamDeck {
+    /// Create an async handle from a connected device, starting the worker thread
+    pub fn new(mut deck: StreamDeck) -> Self {
+        let kind = deck.kind();
+        let (jobs, rx) = channel::<Job>();
+
+        std::thread::spawn(move || {
+            for job in rx {
+                job(&mut deck);
+            }
+        });
+
+        Self { kind, jobs }
+    }
+
+    /// Fetch the device kind
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// Run the provided closure with the device on the worker thread, returning the result
+    ///
+    /// Panics in the closure are resumed when the result is awaited.
+    pub async fn with<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut StreamDeck) -> R + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            value: None,
+            waker: None,
+        }));
+
+        let s = slot.clone();
+        let job: Job = Box::new(move |deck| {
+            let r = catch_unwind(AssertUnwindSafe(|| f(deck)));
+            let mut slot = s.lock().unwrap_or_else(|e| e.into_inner());
+            slot.value = Some(r);
+            if let Some(w) = slot.waker.take() {
+                w.wake();
+            }
+        });
+
+        // The worker only exits once all handles are dropped
+        if self.jobs.send(job).is_err() {
+            unreachable!("async device worker stopped");
+        }
+
+        match (Reply { slot }).await {
+            Ok(r) => r,
+            Err(p) => resume_unwind(p),
+        }
+    }
+
+    /// See [StreamDeck::read_buttons]
+    pub async fn read_buttons(&self, timeout: Option<Duration>) -> Result<ButtonStates, Error> {
+        self.with(move |d| d.read_buttons(timeout)).await
+    }
+
+    /// See [StreamDeck::read_input_report]
+    pub async fn read_input_report(&self, timeout: Option<Duration>) -> Result<InputReport, Error> {
+        self.with(move |d| d.read_input_report(timeout)).await
+    }
+
+    /// See [StreamDeck::set_brightness]
+    pub async fn set_brightness(&self, brightness: u8) -> Result<(), Error> {
+        self.with(move |d| d.set_brightness(brightness)).await
+    }
+
+    /// See [StreamDeck::set_button_rgb]
+    pub async fn set_button_rgb(&self, key: u8, colour: impl Into<Colour>) -> Result<(), Error> {
+        let colour = colour.into();
+        self.with(move |d| d.set_button_rgb(key, colour)).await
+    }
+
+    /// See [StreamDeck::set_button_image]
+    pub async fn set_button_image(&self, key: u8, image: DynamicImage) -> Result<(), Error> {
+        self.with(move |d| d.set_button_image(key, image)).await
+    }
+
+    /// See [StreamDeck::flush]
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.with(|d| d.flush()).await
+    }
+
+    /// See [StreamDeck::reset]
+    pub async fn reset(&self) -> Result<(), Error> {
+        self.with(|d| d.reset()).await
+    }
+}
+
+impl From<StreamDeck> for AsyncStreamDeck {
+    fn from(deck: StreamDeck) -> Self {
+        Self::new(deck)
+    }
+}
+
+/// Result of a call, or the panic payload where the call panicked
+type Outcome<R> = Result<R, Box<dyn Any + Send>>;
+
+/// Result shared between the worker thread and the awaiting task
+struct Slot<R> {
+    value: Option<Outcome<R>>,
+    waker: Option<Waker>,
+}
+
+/// Future resolving once the worker has run a call
+struct Reply<R> {
+    slot: Arc<Mutex<Slot<R>>>,
+}
+
+impl<R> Future for Reply<R> {
+    type Output = Outcome<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap_or_else(|e| e.into_inner());
+
+        match slot.value.take() {
+            Some(v) => Poll::Ready(v),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockTransport;
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::thread::Thread;
+
+    /// Minimal executor, parking the thread until woken
+    fn block_on<F: Future>(f: F) -> F::Output {
+        struct Unpark(Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut f = Box::pin(f);
+        loop {
+            match f.as_mut().poll(&mut cx) {
+                Poll::Ready(v) => return v,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn async_calls() {
+        let mock = MockTransport::new();
+        let deck = AsyncStreamDeck::new(StreamDeck::with_transport(mock.clone(), Kind::Mini));
+        assert_eq!(deck.kind(), Kind::Mini);
+
+        mock.push_input(&[0x01, 0, 0, 1, 0, 0, 0]);
+        block_on(async {
+            let buttons = deck.read_buttons(None).await.unwrap();
+            assert_eq!(buttons.pressed_indices().collect::<Vec<_>>(), vec![2]);
+            assert!(matches!(deck.read_buttons(None).await, Err(Error::NoData)));
+            assert!(matches!(
+                deck.read_input_report(None).await,
+                Err(Error::NoData)
+            ));
+
+            deck.set_button_rgb(2, Colour::RED).await.unwrap();
+            assert!(matches!(
+                deck.set_button_rgb(6, Colour::RED).await,
+                Err(Error::InvalidKeyIndex)
+            ));
+            assert_eq!(deck.with(|d| d.keys()).await, 6);
+        });
+
+        let expected = MockTransport::new();
+        StreamDeck::with_transport(expected.clone(), Kind::Mini)
+            .set_button_rgb(2, Colour::RED)
+            .unwrap();
+        assert_eq!(mock.written_packets(), expected.written_packets());
+
+        // Panics are resumed in the caller, leaving the device usable
+        let r = catch_unwind(AssertUnwindSafe(|| block_on(deck.with(|_| panic!("oops")))));
+        assert!(r.is_err());
+        block_on(deck.set_brightness(50)).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+//! Per-key handles, wrapping the key indexed [StreamDeck] methods, and per-dial handles
+
+use image::DynamicImage;
+
+use crate::{Colour, DialAction, Error, ImageOptions, InputEvent, StreamDeck};
+
+/// Handle to a single key on a device, see [StreamDeck::button]
+///
+/// The key index is validated when the handle is created.
+pub struct Button<'a> {
+    deck: &'a mut StreamDeck,
+    key: u8,
+}
+
+impl<'a> Button<'a> {
+    /// Fetch the key index for this button
+    pub fn index(&self) -> u8 {
+        self.key
+    }
+
+    /// Set the button to the provided colour
+    pub fn set_colour(&mut self, colour: &Colour) -> Result<(), Error> {
+        self.deck.set_button_rgb(self.key, colour)
+    }
+
+    /// Set the button to the provided image, see [StreamDeck::set_button_image]
+    pub fn set_image(&mut self, image: DynamicImage) -> Result<(), Error> {
+        self.deck.set_button_image(self.key, image)
+    }
+
+    /// Set the button to the provided image file, see [StreamDeck::set_button_file]
+    pub fn set_file(&mut self, path: &str, opts: &ImageOptions) -> Result<(), Error> {
+        self.deck.set_button_file(self.key, path, opts)
+    }
+
+    /// Clear the button
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.deck.clear_button(self.key)
+    }
+}
+
+/// Handle to a single dial on a device, see [StreamDeck::dial]
+///
+/// The dial index is validated when the handle is created. Dials are input only,
+/// so the handle is used to pick out input events for the dial.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dial {
+    index: u8,
+}
+
+impl Dial {
+    /// Fetch the index of this dial
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Fetch the dial action from an input event, where the event is for this dial
+    pub fn action(&self, event: &InputEvent) -> Option<DialAction> {
+        match event {
+            InputEvent::Dial { index, action } if *index == self.index => Some(*action),
+            _ => None,
+        }
+    }
+}
+
+impl StreamDeck {
+    /// Fetch a handle for the provided key
+    ///
+    /// Returns [Error::InvalidKeyIndex] if the key does not exist on the device.
+    pub fn button(&mut self, key: u8) -> Result<Button<'_>, Error> {
+        if key >= self.kind.keys() {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        Ok(Button { deck: self, key })
+    }
+
+    /// Fetch a handle for the provided dial
+    ///
+    /// Returns [Error::InvalidDialIndex] if the dial does not exist on the device.
+    pub fn dial(&self, index: u8) -> Result<Dial, Error> {
+        if index >= self.kind.dials() {
+            return Err(Error::InvalidDialIndex);
+        }
+
+        Ok(Dial { index })
+    }
+
+    /// Clear a button, setting it to black
+    pub fn clear_button(&mut self, key: u8) -> Result<(), Error> {
+        self.set_button_rgb(key, &Colour::BLACK)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Kind, MockTransport};
+
+    #[test]
+    fn button_handles() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Mini);
+
+        assert!(matches!(deck.button(6), Err(Error::InvalidKeyIndex)));
+
+        let mut button = deck.button(3).unwrap();
+        assert_eq!(button.index(), 3);
+        button.set_colour(&Colour::GREEN).unwrap();
+        button.clear().unwrap();
+
+        let packets = mock.written_packets();
+        assert!(!packets.is_empty());
+        assert!(packets.iter().all(|p| p[5] == 3));
+        assert_eq!(
+            deck.export_layout().keys[3],
+            Some(crate::KeyContent::Colour(Colour::BLACK))
+        );
+    }
+
+    #[test]
+    fn dial_handles() {
+        let deck = StreamDeck::with_transport(MockTransport::new(), Kind::Studio);
+        assert!(matches!(deck.dial(2), Err(Error::InvalidDialIndex)));
+
+        let dial = deck.dial(1).unwrap();
+        assert_eq!(dial.index(), 1);
+
+        let turned = |index| InputEvent::Dial {
+            index,
+            action: DialAction::Turned(2),
+        };
+        assert_eq!(dial.action(&turned(1)), Some(DialAction::Turned(2)));
+        assert_eq!(dial.action(&turned(0)), None);
+        assert_eq!(dial.action(&InputEvent::Idle), None);
+
+        let deck = StreamDeck::with_transport(MockTransport::new(), Kind::Xl);
+        assert!(matches!(deck.dial(0), Err(Error::InvalidDialIndex)));
+    }
+}
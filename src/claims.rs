@@ -0,0 +1,165 @@
+//! Opt-in key ownership, for coordinating components writing to the same device
+//!
+//! Once a key is claimed with [StreamDeck::claim_key], writes to the key are rejected
+//! with [Error::KeyClaimed] unless made through the [KeyClaim]. Keys that have not
+//! been claimed may be written by anyone.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use image::DynamicImage;
+
+use crate::{Colour, Error, StreamDeck};
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+/// Claim token and owner name
+type Owner = (u64, String);
+
+/// Current owner of each key, shared between a device and its claims
+#[derive(Clone)]
+pub(crate) struct KeyOwners {
+    owners: Arc<Mutex<Vec<Option<Owner>>>>,
+}
+
+impl KeyOwners {
+    pub(crate) fn new(keys: u8) -> Self {
+        Self {
+            owners: Arc::new(Mutex::new(vec![None; keys as usize])),
+        }
+    }
+
+    fn owners(&self) -> MutexGuard<'_, Vec<Option<Owner>>> {
+        // Owners are only modified under the lock, so a poisoned lock is still consistent
+        self.owners.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Check whether a write to the provided key is permitted for the provided token
+    pub(crate) fn check(&self, key: u8, token: Option<u64>) -> Result<(), Error> {
+        match self.owners().get(key as usize) {
+            Some(Some((t, owner))) if Some(*t) != token => Err(Error::KeyClaimed {
+                key,
+                owner: owner.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Claim on a key, see [StreamDeck::claim_key]
+///
+/// The key is released when this is dropped.
+pub struct KeyClaim {
+    key: u8,
+    token: u64,
+    owners: KeyOwners,
+}
+
+impl KeyClaim {
+    /// Fetch the claimed key index
+    pub fn index(&self) -> u8 {
+        self.key
+    }
+
+    /// Run the provided closure with writes to the claimed key permitted
+    ///
+    /// Writes to other keys claimed by different owners are still rejected.
+    pub fn with<R, F>(&self, deck: &mut StreamDeck, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut StreamDeck) -> Result<R, Error>,
+    {
+        let previous = deck.claim_token.replace(self.token);
+        let r = f(deck);
+        deck.claim_token = previous;
+        r
+    }
+
+    /// Set the claimed key to the provided colour, see [StreamDeck::set_button_rgb]
+    pub fn set_colour(&self, deck: &mut StreamDeck, colour: &Colour) -> Result<(), Error> {
+        self.with(deck, |d| d.set_button_rgb(self.key, colour))
+    }
+
+    /// Set the claimed key to the provided image, see [StreamDeck::set_button_image]
+    pub fn set_image(&self, deck: &mut StreamDeck, image: DynamicImage) -> Result<(), Error> {
+        self.with(deck, |d| d.set_button_image(self.key, image))
+    }
+}
+
+impl Drop for KeyClaim {
+    fn drop(&mut self) {
+        let mut owners = self.owners.owners();
+        if let Some(o) = owners.get_mut(self.key as usize) {
+            if matches!(o, Some((t, _)) if *t == self.token) {
+                *o = None;
+            }
+        }
+    }
+}
+
+impl StreamDeck {
+    /// Claim a key for the named owner, rejecting writes to the key from other owners
+    /// until the returned [KeyClaim] is dropped
+    ///
+    /// Returns [Error::KeyClaimed] if the key is already claimed.
+    pub fn claim_key(&self, key: u8, owner: &str) -> Result<KeyClaim, Error> {
+        if key >= self.kind.keys() {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        let mut owners = self.key_owners.owners();
+        if let Some((_, current)) = &owners[key as usize] {
+            return Err(Error::KeyClaimed {
+                key,
+                owner: current.clone(),
+            });
+        }
+
+        let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        owners[key as usize] = Some((token, owner.to_string()));
+
+        Ok(KeyClaim {
+            key,
+            token,
+            owners: self.key_owners.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Kind, MockTransport};
+
+    #[test]
+    fn key_claims() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Mini);
+
+        let claim = deck.claim_key(2, "status").unwrap();
+        assert!(matches!(
+            deck.claim_key(2, "other"),
+            Err(Error::KeyClaimed { key: 2, ref owner }) if owner == "status"
+        ));
+        assert!(matches!(
+            deck.claim_key(6, "other"),
+            Err(Error::InvalidKeyIndex)
+        ));
+
+        // Unowned writes to the claimed key are rejected, other keys are unaffected
+        assert!(matches!(
+            deck.set_button_rgb(2, &Colour::RED),
+            Err(Error::KeyClaimed { key: 2, .. })
+        ));
+        assert!(mock.written_packets().is_empty());
+        deck.set_button_rgb(1, &Colour::RED).unwrap();
+
+        mock.clear();
+        claim.set_colour(&mut deck, &Colour::GREEN).unwrap();
+        assert!(mock.written_packets().iter().all(|p| p[5] == 2));
+
+        // Dropping the claim releases the key
+        drop(claim);
+        deck.set_button_rgb(2, &Colour::BLUE).unwrap();
+        let _claim = deck.claim_key(2, "other").unwrap();
+    }
+}
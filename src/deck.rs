@@ -0,0 +1,240 @@
+//! Model independent device interface, for code that is generic over devices
+//!
+//! [Deck] is implemented by [StreamDeck] and [SharedStreamDeck], and may be implemented
+//! by other types (such as test doubles) to be driven by the same code. Model specific
+//! features remain available on the concrete types.
+
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+
+use crate::{
+    ButtonStates, Capabilities, Colour, Error, InputEvent, InputReport, InputState, Kind,
+    SharedStreamDeck, StreamDeck,
+};
+
+/// Common device operations, see the [module](crate::deck) documentation
+pub trait Deck {
+    /// Fetch the device kind
+    fn kind(&self) -> Kind;
+
+    /// Fetch the number of keys
+    fn keys(&self) -> u8 {
+        self.kind().keys()
+    }
+
+    /// Fetch the device capabilities, including the key layout
+    fn capabilities(&self) -> Capabilities {
+        self.kind().capabilities()
+    }
+
+    /// See [StreamDeck::set_brightness]
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), Error>;
+
+    /// See [StreamDeck::set_button_rgb]
+    fn set_button_rgb(&mut self, key: u8, colour: &Colour) -> Result<(), Error>;
+
+    /// See [StreamDeck::set_button_image]
+    fn set_button_image(&mut self, key: u8, image: DynamicImage) -> Result<(), Error>;
+
+    /// See [StreamDeck::flush]
+    fn flush(&mut self) -> Result<(), Error>;
+
+    /// See [StreamDeck::read_buttons]
+    fn read_buttons(&mut self, timeout: Option<Duration>) -> Result<ButtonStates, Error>;
+
+    /// See [StreamDeck::read_input_report]
+    ///
+    /// By default this reads button states, for devices without dials.
+    fn read_input_report(&mut self, timeout: Option<Duration>) -> Result<InputReport, Error> {
+        self.read_buttons(timeout)
+            .map(|b| InputReport::Buttons(b.into()))
+    }
+
+    /// Read input and return any resulting events, tracked with the provided state
+    ///
+    /// Reads that time out without data return no events rather than an error,
+    /// as with [InputManager::handle_input](crate::InputManager::handle_input).
+    fn read_events(
+        &mut self,
+        state: &mut InputState,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<InputEvent>, Error> {
+        let report = match self.read_input_report(timeout) {
+            Ok(r) => Some(r),
+            Err(Error::NoData) => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok(state.update_report(report.as_ref(), Instant::now()))
+    }
+}
+
+impl Deck for StreamDeck {
+    fn kind(&self) -> Kind {
+        StreamDeck::kind(self)
+    }
+
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), Error> {
+        StreamDeck::set_brightness(self, brightness)
+    }
+
+    fn set_button_rgb(&mut self, key: u8, colour: &Colour) -> Result<(), Error> {
+        StreamDeck::set_button_rgb(self, key, colour)
+    }
+
+    fn set_button_image(&mut self, key: u8, image: DynamicImage) -> Result<(), Error> {
+        StreamDeck::set_button_image(self, key, image)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        StreamDeck::flush(self)
+    }
+
+    fn read_buttons(&mut self, timeout: Option<Duration>) -> Result<ButtonStates, Error> {
+        StreamDeck::read_buttons(self, timeout)
+    }
+
+    fn read_input_report(&mut self, timeout: Option<Duration>) -> Result<InputReport, Error> {
+        StreamDeck::read_input_report(self, timeout)
+    }
+}
+
+impl Deck for SharedStreamDeck {
+    fn kind(&self) -> Kind {
+        SharedStreamDeck::kind(self)
+    }
+
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), Error> {
+        SharedStreamDeck::set_brightness(self, brightness)
+    }
+
+    fn set_button_rgb(&mut self, key: u8, colour: &Colour) -> Result<(), Error> {
+        SharedStreamDeck::set_button_rgb(self, key, colour)
+    }
+
+    fn set_button_image(&mut self, key: u8, image: DynamicImage) -> Result<(), Error> {
+        SharedStreamDeck::set_button_image(self, key, image)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        SharedStreamDeck::flush(self)
+    }
+
+    fn read_buttons(&mut self, timeout: Option<Duration>) -> Result<ButtonStates, Error> {
+        SharedStreamDeck::read_buttons(self, timeout)
+    }
+
+    fn read_input_report(&mut self, timeout: Option<Duration>) -> Result<InputReport, Error> {
+        SharedStreamDeck::read_input_report(self, timeout)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ButtonAction, DialAction, DialInput, MockTransport};
+
+    /// Generic code under test, lighting pressed keys
+    fn light_pressed(deck: &mut dyn Deck, state: &mut InputState) -> Result<(), Error> {
+        for event in deck.read_events(state, None)? {
+            if let InputEvent::Button {
+                index,
+                action: ButtonAction::Pressed,
+            } = event
+            {
+                deck.set_button_rgb(index, &Colour::RED)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Test double recording colours set
+    struct FakeDeck {
+        reports: Vec<Vec<u8>>,
+        colours: Vec<(u8, Colour)>,
+    }
+
+    impl Deck for FakeDeck {
+        fn kind(&self) -> Kind {
+            Kind::Mini
+        }
+
+        fn set_brightness(&mut self, _brightness: u8) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn set_button_rgb(&mut self, key: u8, colour: &Colour) -> Result<(), Error> {
+            self.colours.push((key, colour.clone()));
+            Ok(())
+        }
+
+        fn set_button_image(&mut self, _key: u8, _image: DynamicImage) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn read_buttons(&mut self, _timeout: Option<Duration>) -> Result<ButtonStates, Error> {
+            match self.reports.is_empty() {
+                true => Err(Error::NoData),
+                false => Ok(ButtonStates::new(self.reports.remove(0))),
+            }
+        }
+    }
+
+    #[test]
+    fn generic_decks() {
+        let mut fake = FakeDeck {
+            reports: vec![vec![0, 1, 0, 0, 0, 0]],
+            colours: Vec::new(),
+        };
+        let mut state = InputState::new(fake.keys(), Instant::now());
+        light_pressed(&mut fake, &mut state).unwrap();
+        light_pressed(&mut fake, &mut state).unwrap();
+        assert_eq!(fake.colours, vec![(1, Colour::RED)]);
+        assert_eq!(fake.capabilities().columns, 3);
+
+        // The same code drives a device
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Mini);
+        let mut state = InputState::new(Deck::keys(&deck), Instant::now());
+        mock.push_input(&[0x01, 0, 1, 0, 0, 0, 0]);
+        light_pressed(&mut deck, &mut state).unwrap();
+
+        let expected = MockTransport::new();
+        StreamDeck::with_transport(expected.clone(), Kind::Mini)
+            .set_button_rgb(1, &Colour::RED)
+            .unwrap();
+        assert_eq!(mock.written_packets(), expected.written_packets());
+    }
+
+    #[test]
+    fn generic_dial_events() {
+        let mock = MockTransport::new();
+        let mut deck =
+            SharedStreamDeck::new(StreamDeck::with_transport(mock.clone(), Kind::Studio));
+        let mut state = InputState::new(Deck::keys(&deck), Instant::now());
+
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x01, 0x00, 0x03]);
+        assert_eq!(
+            deck.read_events(&mut state, None).unwrap(),
+            vec![InputEvent::Dial {
+                index: 1,
+                action: DialAction::Turned(3)
+            }]
+        );
+
+        // Dial input may also be fed in directly
+        let events = state.update_dials(&DialInput::Pressed(vec![true, false]), Instant::now());
+        assert_eq!(
+            events,
+            vec![InputEvent::Dial {
+                index: 0,
+                action: DialAction::Pressed
+            }]
+        );
+    }
+}
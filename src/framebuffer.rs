@@ -0,0 +1,182 @@
+//! Per-key framebuffers, for drawing key images directly
+
+use image::{DynamicImage, RgbImage};
+
+use crate::{Colour, DeviceImage, Error, Kind, Rotation, StreamDeck};
+
+/// RGB pixel buffer for a single key, see [StreamDeck::key_framebuffer]
+///
+/// Pixels are 3 bytes (red, green, blue) in rows from the top left of the key as
+/// seen by the user, device rotation and colour order are applied on commit.
+/// The encoded image is kept between commits, so it is only re-encoded once drawn to.
+pub struct KeyFramebuffer {
+    key: u8,
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+    /// Image encoded by the last commit, along with the device kind and orientation it
+    /// was encoded for, cleared when the framebuffer is drawn to
+    encoded: Option<(Kind, Rotation, DeviceImage)>,
+}
+
+impl KeyFramebuffer {
+    /// Fetch the key index for this framebuffer
+    pub fn index(&self) -> u8 {
+        self.key
+    }
+
+    /// Width of the framebuffer in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the framebuffer in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Fetch the pixel data
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Fetch the pixel data for drawing
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.encoded = None;
+        &mut self.data
+    }
+
+    /// Check whether the framebuffer has been drawn to since it was last committed
+    pub fn is_dirty(&self) -> bool {
+        self.encoded.is_none()
+    }
+
+    /// Set a single pixel, pixels outside the framebuffer are ignored
+    pub fn put_pixel(&mut self, x: usize, y: usize, colour: &Colour) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        let i = (y * self.width + x) * 3;
+        self.data[i..i + 3].copy_from_slice(&[colour.r, colour.g, colour.b]);
+        self.encoded = None;
+    }
+
+    /// Fill the framebuffer with a single colour
+    pub fn fill(&mut self, colour: &Colour) {
+        for p in self.data.chunks_exact_mut(3) {
+            p.copy_from_slice(&[colour.r, colour.g, colour.b]);
+        }
+        self.encoded = None;
+    }
+
+    /// Upload the framebuffer to the key, see [StreamDeck::write_button_image]
+    ///
+    /// The framebuffer may be committed more than once, and must match the image size of the device.
+    /// The image is only re-encoded where the framebuffer has been drawn to (see
+    /// [KeyFramebuffer::is_dirty]) or the device or its orientation has changed.
+    pub fn commit(&mut self, deck: &mut StreamDeck) -> Result<(), Error> {
+        if (self.width, self.height) != deck.kind().image_size() {
+            return Err(Error::InvalidImageSize {
+                got: self.data.len(),
+                expected: deck.kind().key_image_bytes_len(),
+            });
+        }
+
+        let (kind, orientation) = (deck.kind(), deck.orientation());
+        let image = match self.encoded.take() {
+            Some((k, o, image)) if k == kind && o == orientation => image,
+            _ => {
+                let image =
+                    RgbImage::from_raw(self.width as u32, self.height as u32, self.data.clone())
+                        .ok_or(Error::InvalidImageSize {
+                            got: self.data.len(),
+                            expected: self.width * self.height * 3,
+                        })?;
+
+                deck.encode_image(DynamicImage::ImageRgb8(image))?
+            }
+        };
+
+        let res = deck.write_button_image(self.key, &image);
+        self.encoded = Some((kind, orientation, image));
+        res
+    }
+}
+
+impl StreamDeck {
+    /// Create a black framebuffer sized for the provided key
+    ///
+    /// Returns [Error::InvalidKeyIndex] if the key does not exist on the device.
+    pub fn key_framebuffer(&self, key: u8) -> Result<KeyFramebuffer, Error> {
+        if key >= self.kind.keys() {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        let (width, height) = self.kind.image_size();
+
+        Ok(KeyFramebuffer {
+            key,
+            width,
+            height,
+            data: vec![0u8; width * height * 3],
+            encoded: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Kind, MockTransport};
+
+    #[test]
+    fn framebuffer_commit() {
+        let kind = Kind::Xl;
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), kind);
+
+        assert!(matches!(
+            deck.key_framebuffer(32),
+            Err(Error::InvalidKeyIndex)
+        ));
+
+        let mut fb = deck.key_framebuffer(4).unwrap();
+        assert_eq!((fb.width(), fb.height()), kind.image_size());
+        assert_eq!(fb.as_mut_slice().len(), kind.key_image_bytes_len());
+
+        fb.fill(&Colour::RED);
+        fb.put_pixel(0, 0, &Colour::WHITE);
+        fb.put_pixel(96, 0, &Colour::WHITE);
+        assert_eq!(&fb.as_slice()[..6], &[255, 255, 255, 255, 0, 0]);
+        fb.commit(&mut deck).unwrap();
+
+        // Matches uploading the equivalent image
+        let (w, h) = kind.image_size();
+        let image = RgbImage::from_raw(w as u32, h as u32, fb.as_slice().to_vec()).unwrap();
+        let expected = MockTransport::new();
+        let mut other = StreamDeck::with_transport(expected.clone(), kind);
+        other
+            .set_button_image(4, DynamicImage::ImageRgb8(image))
+            .unwrap();
+        assert_eq!(mock.written_packets(), expected.written_packets());
+
+        // Unchanged framebuffers are uploaded again without re-encoding
+        assert!(!fb.is_dirty());
+        mock.clear();
+        fb.commit(&mut deck).unwrap();
+        assert_eq!(mock.written_packets(), expected.written_packets());
+
+        fb.put_pixel(1, 1, &Colour::BLUE);
+        assert!(fb.is_dirty());
+        fb.commit(&mut deck).unwrap();
+        assert!(!fb.is_dirty());
+
+        // Framebuffers are sized for their device
+        let mut mini = StreamDeck::with_transport(MockTransport::new(), Kind::Mini);
+        assert!(matches!(
+            fb.commit(&mut mini),
+            Err(Error::InvalidImageSize { .. })
+        ));
+    }
+}
@@ -1,15 +1,16 @@
 use std::str::FromStr;
 
 use image::codecs::jpeg::JpegEncoder;
-use image::io::Reader;
-use image::{imageops::FilterType, Pixel, Rgba};
+use image::imageops::{self, ColorMap, FilterType};
+use image::ImageReader;
 use image::{DynamicImage, ExtendedColorType};
+use image::{Pixel, Rgb, RgbImage, Rgba};
 
 use crate::info::{ColourOrder, Mirroring, Rotation};
 use crate::{rgb_to_bgr, Error};
 
 /// Simple Colour object for re-writing backgrounds etc.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Colour {
@@ -23,12 +24,68 @@ pub struct Colour {
     pub b: u8,
 }
 
+impl Colour {
+    pub const BLACK: Colour = Colour { r: 0, g: 0, b: 0 };
+    pub const WHITE: Colour = Colour {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    pub const RED: Colour = Colour { r: 255, g: 0, b: 0 };
+    pub const GREEN: Colour = Colour { r: 0, g: 255, b: 0 };
+    pub const BLUE: Colour = Colour { r: 0, g: 0, b: 255 };
+    pub const YELLOW: Colour = Colour {
+        r: 255,
+        g: 255,
+        b: 0,
+    };
+    pub const CYAN: Colour = Colour {
+        r: 0,
+        g: 255,
+        b: 255,
+    };
+    pub const MAGENTA: Colour = Colour {
+        r: 255,
+        g: 0,
+        b: 255,
+    };
+
+    /// Create a colour from hue (in degrees), saturation and value (0.0 to 1.0)
+    ///
+    /// Hue wraps around at 360 degrees, saturation and value are clamped.
+    pub fn from_hsv(h: f32, s: f32, v: f32) -> Self {
+        let h = h.rem_euclid(360.0) / 60.0;
+        let s = s.clamp(0.0, 1.0);
+        let v = v.clamp(0.0, 1.0);
+
+        let c = v * s;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let m = v - c;
+
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+
+        let scale = |n: f32| ((n + m) * 255.0).round() as u8;
+        Self {
+            r: scale(r),
+            g: scale(g),
+            b: scale(b),
+        }
+    }
+}
+
 impl FromStr for Colour {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 6 && s.len() != 8 {
-            return Err(format!("Expected colour in the hex form: RRGGBB"));
+        if !s.is_ascii() || (s.len() != 6 && s.len() != 8) {
+            return Err("Expected colour in the hex form: RRGGBB".to_string());
         }
 
         let r =
@@ -42,8 +99,68 @@ impl FromStr for Colour {
     }
 }
 
+impl From<&Colour> for Colour {
+    fn from(c: &Colour) -> Self {
+        c.clone()
+    }
+}
+
+impl From<Colour> for Rgb<u8> {
+    fn from(c: Colour) -> Self {
+        Rgb([c.r, c.g, c.b])
+    }
+}
+
+impl From<Rgb<u8>> for Colour {
+    fn from(p: Rgb<u8>) -> Self {
+        let [r, g, b] = p.0;
+        Self { r, g, b }
+    }
+}
+
+/// Converts to an opaque pixel
+impl From<Colour> for Rgba<u8> {
+    fn from(c: Colour) -> Self {
+        Rgba([c.r, c.g, c.b, 255])
+    }
+}
+
+/// Converts discarding the alpha channel
+impl From<Rgba<u8>> for Colour {
+    fn from(p: Rgba<u8>) -> Self {
+        let [r, g, b, _] = p.0;
+        Self { r, g, b }
+    }
+}
+
+/// Scaling applied to fit images to the key size
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ScaleMode {
+    /// Stretch to the key size, ignoring the aspect ratio
+    #[default]
+    Stretch,
+    /// Scale to fit within the key, padding with the background colour (or black)
+    Fit,
+    /// Scale to cover the key, cropping the overflow
+    Fill,
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stretch" => Ok(ScaleMode::Stretch),
+            "fit" => Ok(ScaleMode::Fit),
+            "fill" => Ok(ScaleMode::Fill),
+            _ => Err("Expected scale mode: stretch, fit or fill".to_string()),
+        }
+    }
+}
+
 /// Options for image loading and editing
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Default)]
 #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct ImageOptions {
@@ -54,20 +171,100 @@ pub struct ImageOptions {
     #[cfg_attr(feature = "structopt", structopt(long))]
     /// Invert colours
     invert: bool,
+
+    #[cfg_attr(
+        feature = "structopt",
+        structopt(long, default_value = "fit", possible_values = &["stretch", "fit", "fill"])
+    )]
+    #[cfg_attr(feature = "serde", serde(default))]
+    /// Scaling to the key size, the library default is stretch
+    scale: ScaleMode,
+
+    #[cfg_attr(feature = "structopt", structopt(long))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    /// Dither images to reduce banding on gradients
+    dither: bool,
+
+    #[cfg_attr(feature = "structopt", structopt(skip))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    /// Per-channel (red, green, blue) multipliers, unity where not set
+    gain: Option<[f32; 3]>,
 }
 
 impl ImageOptions {
     pub fn new(background: Option<Colour>, invert: bool) -> Self {
-        ImageOptions { background, invert }
+        ImageOptions {
+            background,
+            invert,
+            scale: ScaleMode::default(),
+            dither: false,
+            gain: None,
+        }
+    }
+
+    /// Options for logos, scaled to fit on a black background
+    pub fn logo() -> Self {
+        Self::new(Some(Colour::BLACK), false).with_scale(ScaleMode::Fit)
+    }
+
+    /// Options for photos, scaled to fill the key
+    pub fn fill() -> Self {
+        Self::default().with_scale(ScaleMode::Fill)
+    }
+
+    /// Options for icons already sized for keys, stretched to the key size
+    ///
+    /// This is equivalent to [ImageOptions::default].
+    pub fn icon() -> Self {
+        Self::default()
+    }
+
+    /// Set the scale mode
+    pub fn with_scale(mut self, scale: ScaleMode) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Multiply each colour channel by the provided gain, for tinting images
+    ///
+    /// Gains of 1.0 leave the channel unchanged, results are clamped to the channel range.
+    pub fn channel_gain(mut self, r: f32, g: f32, b: f32) -> Self {
+        self.gain = Some([r, g, b]);
+        self
+    }
+
+    /// Enable Floyd–Steinberg dithering, reducing visible banding on gradients
+    ///
+    /// Images are dithered to 5 bits per channel after resizing, spreading the
+    /// quantisation error rather than leaving hard steps between colour bands.
+    pub fn with_dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
     }
 }
 
-impl Default for ImageOptions {
-    fn default() -> Self {
-        Self {
-            background: None,
-            invert: false,
-        }
+/// Colour map reducing each channel to the provided number of bits, for dithering
+struct ChannelDepth(u8);
+
+impl ChannelDepth {
+    fn quantise(&self, c: u8) -> u8 {
+        let levels = (1u32 << self.0) - 1;
+        let q = (c as u32 * levels + 127) / 255;
+        ((q * 255 + levels / 2) / levels) as u8
+    }
+}
+
+impl ColorMap for ChannelDepth {
+    type Color = Rgb<u8>;
+
+    fn index_of(&self, color: &Rgb<u8>) -> usize {
+        let mut c = *color;
+        self.map_color(&mut c);
+        c.0.iter().fold(0, |i, v| i << 8 | *v as usize)
+    }
+
+    fn map_color(&self, color: &mut Rgb<u8>) {
+        color.0.iter_mut().for_each(|c| *c = self.quantise(*c));
     }
 }
 
@@ -82,13 +279,12 @@ pub(crate) fn apply_transform(
         Rotation::Rot180 => image.rotate180(),
         Rotation::Rot270 => image.rotate270(),
     };
-    let image = match mirroring {
+    match mirroring {
         Mirroring::None => image,
         Mirroring::X => image.flipv(),
         Mirroring::Y => image.fliph(),
         Mirroring::Both => image.flipv().fliph(),
-    };
-    image
+    }
 }
 
 /// Load an image from a file, resize to defined x and y, and apply the provided options
@@ -102,7 +298,7 @@ pub(crate) fn load_image(
     colour_order: ColourOrder,
 ) -> Result<Vec<u8>, Error> {
     // Open image reader
-    let reader = match Reader::open(path) {
+    let reader = match ImageReader::open(path) {
         Ok(v) => v,
         Err(e) => {
             error!("error loading file '{}': {:?}", path, e);
@@ -111,12 +307,25 @@ pub(crate) fn load_image(
     };
 
     // Load image
-    let mut image = reader.decode().map_err(Error::Image)?;
+    let image = reader.decode().map_err(Error::Image)?;
+
+    process_image(image, x, y, rotate, mirror, opts, colour_order)
+}
 
+/// Resize an image to defined x and y, apply the provided options, and convert to raw pixels
+pub(crate) fn process_image(
+    mut image: DynamicImage,
+    x: usize,
+    y: usize,
+    rotate: Rotation,
+    mirror: Mirroring,
+    opts: &ImageOptions,
+    colour_order: ColourOrder,
+) -> Result<Vec<u8>, Error> {
     // Apply background filter / replace
     // This must be done before transparency is removed
     if let Some(c) = &opts.background {
-        let rgba = image.as_mut_rgba8().unwrap();
+        let mut rgba = image.to_rgba8();
 
         let mut r = Rgba([c.r, c.g, c.b, 0]);
         if opts.invert {
@@ -128,10 +337,33 @@ pub(crate) fn load_image(
 
             p.blend(&r);
         }
+
+        image = DynamicImage::ImageRgba8(rgba);
     }
 
     // Resize image
-    let mut image = image.resize(x as u32, y as u32, FilterType::Gaussian);
+    let (w, h) = (x as u32, y as u32);
+    let mut image = match opts.scale {
+        ScaleMode::Stretch => image.resize_exact(w, h, FilterType::Gaussian),
+        ScaleMode::Fill => image.resize_to_fill(w, h, FilterType::Gaussian),
+        ScaleMode::Fit => {
+            let scaled = image.resize(w, h, FilterType::Gaussian).to_rgb8();
+            let c = opts.background.clone().unwrap_or(Colour::BLACK);
+
+            // The canvas is inverted along with the image, so is filled with the inverted
+            // background to leave the padding in the background colour
+            let mut bg = Rgb([c.r, c.g, c.b]);
+            if opts.invert {
+                bg.invert();
+            }
+
+            let mut canvas = RgbImage::from_pixel(w, h, bg);
+            let (dx, dy) = ((w - scaled.width()) / 2, (h - scaled.height()) / 2);
+            imageops::overlay(&mut canvas, &scaled, dx as i64, dy as i64);
+
+            DynamicImage::ImageRgb8(canvas)
+        }
+    };
 
     // Apply the requested mirroring transformation
     image = apply_transform(image, rotate, mirror);
@@ -141,14 +373,33 @@ pub(crate) fn load_image(
         image.invert();
     }
 
+    if let Some(gain) = opts.gain {
+        let mut rgb = image.to_rgb8();
+        for p in rgb.pixels_mut() {
+            for (c, g) in p.0.iter_mut().zip(gain.iter()) {
+                *c = (*c as f32 * g).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        image = DynamicImage::ImageRgb8(rgb);
+    }
+
+    if opts.dither {
+        let mut rgb = image.to_rgb8();
+        imageops::dither(&mut rgb, &ChannelDepth(5));
+        image = DynamicImage::ImageRgb8(rgb);
+    }
+
     // Convert to vector with correct encoding
     let mut v = image.to_rgb8().into_vec();
-    if matches!(colour_order, ColourOrder::BGR) {
+    if matches!(colour_order, ColourOrder::Bgr) {
         rgb_to_bgr(&mut v);
     }
 
     if v.len() != x * y * 3 {
-        return Err(Error::InvalidImageSize);
+        return Err(Error::InvalidImageSize {
+            got: v.len(),
+            expected: x * y * 3,
+        });
     }
 
     Ok(v)
@@ -175,8 +426,170 @@ mod test {
             Rotation::Rot180,
             Mirroring::Both,
             &ImageOptions::default(),
-            ColourOrder::BGR,
+            ColourOrder::Bgr,
         )
         .expect("error loading image");
     }
+
+    #[test]
+    fn load_image_scale_modes() {
+        // power.png is square, so check scaling with a wide image
+        let path = std::env::temp_dir().join(format!("streamdeck-wide-{}.png", std::process::id()));
+        RgbImage::from_pixel(40, 20, Rgb([255, 255, 255]))
+            .save(&path)
+            .unwrap();
+        let path = path.to_str().unwrap();
+
+        let load = |opts: &ImageOptions| {
+            load_image(
+                path,
+                10,
+                10,
+                Rotation::Rot0,
+                Mirroring::None,
+                opts,
+                ColourOrder::Rgb,
+            )
+            .unwrap()
+        };
+        let pixel = |v: &[u8], x: usize, y: usize| v[(y * 10 + x) * 3];
+
+        for opts in [ImageOptions::icon(), ImageOptions::fill()].iter() {
+            let v = load(opts);
+            assert_eq!(v.len(), 10 * 10 * 3);
+            assert_eq!((pixel(&v, 5, 0), pixel(&v, 5, 9)), (255, 255));
+        }
+
+        // Fit pads above and below the image
+        let v = load(&ImageOptions::logo());
+        assert_eq!(
+            (pixel(&v, 5, 0), pixel(&v, 5, 5), pixel(&v, 5, 9)),
+            (0, 255, 0)
+        );
+
+        // Padding keeps the background colour when inverting
+        let opts = ImageOptions::new(Some(Colour::RED), true).with_scale(ScaleMode::Fit);
+        let v = load(&opts);
+        assert_eq!(
+            (pixel(&v, 5, 0), pixel(&v, 5, 5), pixel(&v, 5, 9)),
+            (255, 0, 255)
+        );
+        assert_eq!(&v[..3], &[255, 0, 0]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn colour_from_str() {
+        let c = Colour::from_str("0a0B0c").unwrap();
+        assert_eq!((c.r, c.g, c.b), (0x0a, 0x0b, 0x0c));
+
+        assert!(Colour::from_str("0a0b").is_err());
+        assert!(Colour::from_str("zz0000").is_err());
+        // Multi-byte characters must not split on a char boundary
+        assert!(Colour::from_str("\u{e9}\u{e9}\u{e9}").is_err());
+    }
+
+    #[test]
+    fn dither_gradients() {
+        let gradient = DynamicImage::ImageRgb8(RgbImage::from_fn(72, 72, |x, _| {
+            let v = (x * 16 / 72) as u8 + 100;
+            Rgb([v, v, v])
+        }));
+
+        let process = |opts: &ImageOptions| {
+            process_image(
+                gradient.clone(),
+                72,
+                72,
+                Rotation::Rot0,
+                Mirroring::None,
+                opts,
+                ColourOrder::Rgb,
+            )
+            .unwrap()
+        };
+
+        let plain = process(&ImageOptions::default());
+        let dithered = process(&ImageOptions::default().with_dither(true));
+        assert_ne!(plain, dithered);
+
+        // Dithered output only uses the reduced channel depth
+        let depth = ChannelDepth(5);
+        assert!(dithered.iter().all(|c| depth.quantise(*c) == *c));
+        assert!(plain.iter().any(|c| depth.quantise(*c) != *c));
+    }
+
+    #[test]
+    fn channel_gains() {
+        let white = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 8, Rgb([255, 255, 255])));
+        let process = |opts: &ImageOptions| {
+            process_image(
+                white.clone(),
+                8,
+                8,
+                Rotation::Rot0,
+                Mirroring::None,
+                opts,
+                ColourOrder::Rgb,
+            )
+            .unwrap()
+        };
+
+        let red = process(&ImageOptions::default().channel_gain(1.0, 0.0, 0.0));
+        assert!(red.chunks(3).all(|p| p == [255, 0, 0]));
+
+        let dimmed = process(&ImageOptions::default().channel_gain(1.0, 2.0, 0.5));
+        assert!(dimmed.chunks(3).all(|p| p == [255, 255, 128]));
+
+        assert_eq!(
+            process(&ImageOptions::default().channel_gain(1.0, 1.0, 1.0)),
+            process(&ImageOptions::default())
+        );
+    }
+
+    #[test]
+    fn colour_pixel_conversions() {
+        let c = Colour {
+            r: 12,
+            g: 34,
+            b: 56,
+        };
+
+        let rgb: Rgb<u8> = c.clone().into();
+        assert_eq!(rgb, Rgb([12, 34, 56]));
+        assert_eq!(Colour::from(rgb), c);
+
+        let rgba: Rgba<u8> = c.clone().into();
+        assert_eq!(rgba, Rgba([12, 34, 56, 255]));
+        assert_eq!(Colour::from(rgba), c);
+        assert_eq!(Colour::from(Rgba([12, 34, 56, 0])), c);
+    }
+
+    #[test]
+    fn colour_from_hsv() {
+        assert_eq!(Colour::from_hsv(0.0, 1.0, 1.0), Colour::RED);
+        assert_eq!(Colour::from_hsv(120.0, 1.0, 1.0), Colour::GREEN);
+        assert_eq!(Colour::from_hsv(240.0, 1.0, 1.0), Colour::BLUE);
+        assert_eq!(Colour::from_hsv(60.0, 1.0, 1.0), Colour::YELLOW);
+        assert_eq!(Colour::from_hsv(360.0, 1.0, 1.0), Colour::RED);
+        assert_eq!(Colour::from_hsv(0.0, 0.0, 1.0), Colour::WHITE);
+        assert_eq!(Colour::from_hsv(200.0, 1.0, 0.0), Colour::BLACK);
+        assert_eq!(
+            Colour::from_hsv(30.0, 1.0, 1.0),
+            Colour {
+                r: 255,
+                g: 128,
+                b: 0
+            }
+        );
+        assert_eq!(
+            Colour::from_hsv(210.0, 0.5, 0.5),
+            Colour {
+                r: 64,
+                g: 96,
+                b: 128
+            }
+        );
+    }
 }
@@ -1,12 +1,32 @@
+use crate::spec::{self, DeviceSpec};
+use crate::{pids, Error};
+
 /// Stream Deck Device Kinds
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Kind {
+    /// Original 15 key Stream Deck (pid 0x0060), BMP images mirrored horizontally
     Original,
+    /// Second revision of the original 15 key Stream Deck (pid 0x006d),
+    /// JPEG images rotated 180 degrees
     OriginalV2,
     Mini,
     RevisedMini,
     Xl,
     Mk2,
+    /// Stream Deck Pedal (pid 0x0086), 3 pedals without a display
+    Pedal,
+    /// Stream Deck Neo (pid 0x009a), 8 keys with two touch points and an info bar screen
+    Neo,
+    /// Stream Deck Studio (pid 0x00aa), rack mounted with 32 keys and two dials
+    Studio,
+    /// Stream Deck Module 6 (pid 0x00b8), OEM keypad as used by the Mini
+    Module6,
+    /// Stream Deck Module 15 (pid 0x00b9), OEM keypad as used by the MK.2
+    Module15,
+    /// Stream Deck Module 32 (pid 0x00ba), OEM keypad as used by the XL
+    Module32,
+    /// Device described by a custom [DeviceSpec], for compatible hardware not otherwise supported
+    Custom(&'static DeviceSpec),
 }
 
 /// Stream Deck key layout direction
@@ -16,6 +36,15 @@ pub enum KeyDirection {
     RightToLeft,
 }
 
+/// Input report contents, see [Kind::input_types]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum InputType {
+    /// Key states
+    Keys,
+    /// Dial presses or rotation
+    Dials,
+}
+
 /// Stream Deck Image Modes
 #[derive(Debug, Clone, PartialEq)]
 pub enum ImageMode {
@@ -26,8 +55,8 @@ pub enum ImageMode {
 /// Stream Deck color mode
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum ColourOrder {
-    RGB,
-    BGR,
+    Rgb,
+    Bgr,
 }
 
 /// Rotation to apply to an image
@@ -39,6 +68,24 @@ pub enum Rotation {
     Rot270,
 }
 
+impl Rotation {
+    /// Combine with a further rotation (clockwise)
+    pub(crate) fn then(&self, other: &Rotation) -> Rotation {
+        let quarters = |r: &Rotation| match r {
+            Rotation::Rot0 => 0,
+            Rotation::Rot90 => 1,
+            Rotation::Rot180 => 2,
+            Rotation::Rot270 => 3,
+        };
+        match (quarters(self) + quarters(other)) % 4 {
+            0 => Rotation::Rot0,
+            1 => Rotation::Rot90,
+            2 => Rotation::Rot180,
+            _ => Rotation::Rot270,
+        }
+    }
+}
+
 /// Mirroring to apply to an image
 #[derive(Debug, Clone, PartialEq)]
 pub enum Mirroring {
@@ -49,120 +96,253 @@ pub enum Mirroring {
 }
 
 impl Kind {
-    pub fn keys(&self) -> u8 {
+    /// Fetch the device description, see [DeviceSpec]
+    pub fn spec(&self) -> &'static DeviceSpec {
         match self {
-            Kind::Original | Kind::OriginalV2 | Kind::Mk2 => 15,
-            Kind::Mini | Kind::RevisedMini => 6,
-            Kind::Xl => 32,
+            Kind::Original => &spec::ORIGINAL,
+            Kind::OriginalV2 => &spec::ORIGINAL_V2,
+            Kind::Mini => &spec::MINI,
+            Kind::RevisedMini => &spec::REVISED_MINI,
+            Kind::Xl => &spec::XL,
+            Kind::Mk2 => &spec::MK2,
+            Kind::Pedal => &spec::PEDAL,
+            Kind::Neo => &spec::NEO,
+            Kind::Studio => &spec::STUDIO,
+            Kind::Module6 => &spec::MODULE_6,
+            Kind::Module15 => &spec::MODULE_15,
+            Kind::Module32 => &spec::MODULE_32,
+            Kind::Custom(s) => s,
         }
     }
 
-    // Offset for the first key in button report
-    pub(crate) fn key_data_offset(&self) -> usize {
-        match self {
-            Kind::Original => 0,
-            Kind::OriginalV2 | Kind::Mk2 => 3,
-            Kind::Mini | Kind::RevisedMini => 0,
-            Kind::Xl => 3,
+    /// Match a device kind from its USB Product ID (PID)
+    pub fn from_pid(pid: u16) -> Option<Kind> {
+        match pid {
+            pids::ORIGINAL => Some(Kind::Original),
+            pids::MINI => Some(Kind::Mini),
+
+            pids::ORIGINAL_V2 => Some(Kind::OriginalV2),
+            pids::XL | pids::XL_V2 => Some(Kind::Xl),
+            pids::MK2 | pids::MK2_SCISSOR => Some(Kind::Mk2),
+            pids::REVISED_MINI | pids::MINI_DISCORD => Some(Kind::RevisedMini),
+            pids::PEDAL => Some(Kind::Pedal),
+            pids::NEO => Some(Kind::Neo),
+            pids::STUDIO => Some(Kind::Studio),
+            pids::MODULE_6 => Some(Kind::Module6),
+            pids::MODULE_15 => Some(Kind::Module15),
+            pids::MODULE_32 => Some(Kind::Module32),
+
+            _ => None,
         }
     }
 
-    pub(crate) fn key_direction(&self) -> KeyDirection {
-        match self {
-            Kind::Original => KeyDirection::RightToLeft,
-            _ => KeyDirection::LeftToRight,
+    /// Fetch the product name for the device kind
+    pub fn name(&self) -> &'static str {
+        self.spec().name
+    }
+
+    pub fn keys(&self) -> u8 {
+        self.spec().keys
+    }
+
+    /// Number of touch points, reported as buttons following the keys
+    ///
+    /// Touch points have no image, though the colour of each may be set with
+    /// [StreamDeck::set_touch_point_colour](crate::StreamDeck::set_touch_point_colour).
+    pub fn touch_points(&self) -> u8 {
+        self.spec().touch_points
+    }
+
+    /// Number of dials, see [InputEvent::Dial](crate::InputEvent::Dial)
+    pub fn dials(&self) -> u8 {
+        self.spec().dials
+    }
+
+    /// Number of button states in input reports, the keys followed by any touch points
+    pub fn buttons(&self) -> u8 {
+        self.keys() + self.touch_points()
+    }
+
+    /// Offset for the first key in button reports (after the report ID), see
+    /// [StreamDeck::set_key_data_offset](crate::StreamDeck::set_key_data_offset) to override this
+    pub fn key_data_offset(&self) -> usize {
+        self.spec().key_data_offset
+    }
+
+    /// Size of an input report in bytes, including the leading report ID
+    pub fn input_report_size(&self) -> usize {
+        1 + self.key_data_offset() + self.buttons() as usize
+    }
+
+    /// Report ID prefixing input reports
+    pub(crate) fn input_report_id(&self) -> u8 {
+        0x01
+    }
+
+    /// Input report handlers by the leading input type byte (after the report ID),
+    /// empty for devices where input reports have no type byte
+    pub(crate) fn input_types(&self) -> &'static [(u8, InputType)] {
+        match (self.is_v2(), self.dials()) {
+            (false, _) => &[],
+            (true, 0) => &[(0x00, InputType::Keys)],
+            (true, _) => &[(0x00, InputType::Keys), (0x03, InputType::Dials)],
         }
     }
 
+    pub(crate) fn key_direction(&self) -> KeyDirection {
+        self.spec().key_direction
+    }
+
     pub(crate) fn key_index_offset(&self) -> u8 {
-        match self {
-            Kind::RevisedMini => 1,
-            _ => 0,
+        self.spec().key_index_offset
+    }
+
+    /// Info bar screen size in pixels (width, height), for devices with an info bar
+    ///
+    /// See [StreamDeck::set_info_bar_image](crate::StreamDeck::set_info_bar_image).
+    pub fn info_bar_size(&self) -> Option<(usize, usize)> {
+        self.spec().info_bar_size
+    }
+
+    /// Summarise the device capabilities, see [Capabilities]
+    pub fn capabilities(&self) -> Capabilities {
+        let columns = self.key_columns();
+        Capabilities {
+            keys: self.keys(),
+            columns,
+            rows: self.keys() / columns,
+            image_size: self.image_size(),
+            image_mode: self.image_mode(),
+            dials: self.dials(),
         }
     }
 
     pub(crate) fn key_columns(&self) -> u8 {
-        match self {
-            Kind::Mini | Kind::RevisedMini => 3,
-            Kind::Original | Kind::OriginalV2 | Kind::Mk2 => 5,
-            Kind::Xl => 8,
+        self.spec().columns
+    }
+
+    /// Transforms a key from zero-indexed left-to-right into the device-correct coordinate system
+    pub(crate) fn translate_key_index(&self, key: u8) -> Result<u8, Error> {
+        if key >= self.keys() {
+            return Err(Error::InvalidKeyIndex);
         }
+        let mapped = match self.key_direction() {
+            // All but the original Streamdeck already have correct coordinates
+            KeyDirection::LeftToRight => key + self.key_index_offset(),
+            // The original Streamdeck uses 1-indexed right-to-left
+            KeyDirection::RightToLeft => {
+                let cols = self.key_columns();
+                let col = key % cols;
+                let row = key / cols;
+                row * cols + cols - col
+            }
+        };
+        Ok(mapped)
+    }
+
+    /// Usable range of raw device brightness values, mapped to 0-100%
+    ///
+    /// No supported model is known to need a different range, they all take the brightness
+    /// directly as a percentage and report `(0, 100)`. Custom devices may declare their own
+    /// range through [DeviceSpec::brightness_range](crate::DeviceSpec::brightness_range).
+    pub fn brightness_range(&self) -> (u8, u8) {
+        self.spec().brightness_range
+    }
+
+    /// Map a brightness percentage to the raw device value
+    pub fn brightness_raw(&self, percent: u8) -> u8 {
+        let (min, max) = self.brightness_range();
+        let percent = percent.min(100) as u32;
+
+        (min as u32 + max.saturating_sub(min) as u32 * percent / 100) as u8
+    }
+
+    /// Check whether the device has a display, image and brightness calls on
+    /// devices without a display return [Error::NoDisplay]
+    pub fn has_display(&self) -> bool {
+        self.image_size() != (0, 0)
     }
 
     pub fn image_mode(&self) -> ImageMode {
-        match self {
-            Kind::Original | Kind::Mini | Kind::RevisedMini => ImageMode::Bmp,
-            Kind::OriginalV2 | Kind::Xl | Kind::Mk2 => ImageMode::Jpeg,
+        self.spec().image_mode.clone()
+    }
+
+    /// Image formats accepted by the device
+    ///
+    /// Devices accept a single format, so this currently contains only [Kind::image_mode],
+    /// or is empty for devices without a display.
+    pub fn image_formats(&self) -> &'static [ImageMode] {
+        if !self.has_display() {
+            return &[];
+        }
+
+        match self.image_mode() {
+            ImageMode::Bmp => &[ImageMode::Bmp],
+            ImageMode::Jpeg => &[ImageMode::Jpeg],
         }
     }
 
+    /// Check whether the device accepts images in the provided format
+    pub fn supports_image_format(&self, mode: &ImageMode) -> bool {
+        self.image_formats().contains(mode)
+    }
+
     pub fn image_size(&self) -> (usize, usize) {
-        match self {
-            Kind::Original | Kind::OriginalV2 | Kind::Mk2 => (72, 72),
-            Kind::Mini | Kind::RevisedMini => (80, 80),
-            Kind::Xl => (96, 96),
-        }
+        self.spec().image_size
     }
 
     pub fn image_rotation(&self) -> Rotation {
-        match self {
-            Kind::Mini | Kind::RevisedMini => Rotation::Rot270,
-            _ => Rotation::Rot0,
-        }
+        self.spec().image_rotation.clone()
     }
 
     pub fn image_mirror(&self) -> Mirroring {
-        match self {
-            // Mini has rotation, not mirror
-            Kind::Mini | Kind::RevisedMini => Mirroring::None,
-            // On the original the image is flipped across the Y axis
-            Kind::Original => Mirroring::Y,
-            // On the V2 devices, both X and Y need to flip
-            Kind::OriginalV2 | Kind::Xl | Kind::Mk2 => Mirroring::Both,
-        }
+        self.spec().image_mirror.clone()
     }
 
-    pub fn image_size_bytes(&self) -> usize {
+    /// Length of a raw key image in bytes, as accepted by
+    /// [StreamDeck::set_button_raw](crate::StreamDeck::set_button_raw)
+    ///
+    /// This is 3 bytes (one per colour channel, in the device colour order) per pixel of [Kind::image_size],
+    /// prior to any BMP header or JPEG encoding applied for the device.
+    pub fn key_image_bytes_len(&self) -> usize {
         let (x, y) = self.image_size();
         x * y * 3
     }
 
+    #[deprecated(note = "use key_image_bytes_len")]
+    pub fn image_size_bytes(&self) -> usize {
+        self.key_image_bytes_len()
+    }
+
     pub(crate) fn image_report_len(&self) -> usize {
-        match self {
-            Kind::Original => 8191,
-            _ => 1024,
-        }
+        self.spec().image_report_len
+    }
+
+    /// Maximum image payload bytes in each image upload packet
+    pub fn max_upload_chunk_size(&self) -> usize {
+        self.image_report_len() - self.image_report_header_len()
     }
 
     pub(crate) fn image_report_header_len(&self) -> usize {
-        match self {
-            Kind::Original | Kind::Mini | Kind::RevisedMini => 16,
-            Kind::OriginalV2 | Kind::Xl | Kind::Mk2 => 8,
+        match self.is_v2() {
+            true => 8,
+            false => 16,
         }
     }
 
-    pub fn image_base(&self) -> &[u8] {
-        match self {
-            // BMP headers for the original and mini
-            Kind::Original => &ORIGINAL_IMAGE_BASE,
-            Kind::Mini | Kind::RevisedMini => &MINI_IMAGE_BASE,
-
-            Kind::OriginalV2 | Kind::Xl | Kind::Mk2 => &[],
-        }
+    pub fn image_base(&self) -> &'static [u8] {
+        self.spec().image_base
     }
 
     pub(crate) fn image_colour_order(&self) -> ColourOrder {
-        match self {
-            Kind::Original | Kind::Mini | Kind::RevisedMini => ColourOrder::BGR,
-            Kind::OriginalV2 | Kind::Xl | Kind::Mk2 => ColourOrder::RGB,
+        match self.image_mode() {
+            ImageMode::Bmp => ColourOrder::Bgr,
+            ImageMode::Jpeg => ColourOrder::Rgb,
         }
     }
 
     pub(crate) fn is_v2(&self) -> bool {
-        match self {
-            Kind::OriginalV2 | Kind::Xl | Kind::Mk2 => true,
-            _ => false,
-        }
+        self.spec().v2
     }
 }
 
@@ -173,9 +353,400 @@ pub const ORIGINAL_IMAGE_BASE: [u8; 54] = [
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
 
-const MINI_IMAGE_BASE: [u8; 54] = [
+pub(crate) const MINI_IMAGE_BASE: [u8; 54] = [
     0x42, 0x4d, 0xf6, 0x3c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x36, 0x00, 0x00, 0x00, 0x28, 0x00,
     0x00, 0x00, 0x48, 0x00, 0x00, 0x00, 0x48, 0x00, 0x00, 0x00, 0x01, 0x00, 0x18, 0x00, 0x00, 0x00,
     0x00, 0x00, 0xc0, 0x3c, 0x00, 0x00, 0xc4, 0x0e, 0x00, 0x00, 0xc4, 0x0e, 0x00, 0x00, 0x00, 0x00,
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
+
+/// Summary of device capabilities, see [Kind::capabilities]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    /// Number of keys
+    pub keys: u8,
+    /// Number of key columns
+    pub columns: u8,
+    /// Number of key rows
+    pub rows: u8,
+    /// Key image size in pixels (width, height), zero for devices without a display
+    pub image_size: (usize, usize),
+    /// Key image format
+    pub image_mode: ImageMode,
+    /// Number of dials
+    pub dials: u8,
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} keys ({}x{}), ", self.keys, self.columns, self.rows)?;
+
+        match self.image_size {
+            (0, 0) => write!(f, "no display")?,
+            (w, h) => write!(f, "{}x{} {:?} images", w, h, self.image_mode)?,
+        }
+
+        write!(f, ", dials: {}", self.dials)
+    }
+}
+
+/// Device firmware version, see [StreamDeck::firmware_version](crate::StreamDeck::firmware_version)
+///
+/// Versions are ordered by their numeric components. Where the version string is not
+/// in the expected dotted numeric form (eg. `1.00.006`) the components are zero, and
+/// the original string is kept in `raw`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub raw: String,
+}
+
+impl FirmwareVersion {
+    /// Parse a firmware version string
+    pub fn parse(raw: &str) -> Self {
+        let parts: Option<Vec<u32>> = raw
+            .trim()
+            .split('.')
+            .map(|p| p.parse::<u32>().ok())
+            .collect();
+
+        let (major, minor, patch) = match parts.as_deref() {
+            Some([major, minor]) => (*major, *minor, 0),
+            Some([major, minor, patch]) => (*major, *minor, *patch),
+            _ => {
+                debug!("Unrecognised firmware version format: {:?}", raw);
+                (0, 0, 0)
+            }
+        };
+
+        Self {
+            major,
+            minor,
+            patch,
+            raw: raw.to_string(),
+        }
+    }
+
+    /// Check whether the version string was parsed
+    pub fn is_parsed(&self) -> bool {
+        (self.major, self.minor, self.patch) != (0, 0, 0)
+    }
+}
+
+impl std::fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Device identifiers and capabilities as reported by real hardware
+    struct Vector {
+        pid: u16,
+        product: &'static str,
+        kind: Kind,
+        keys: u8,
+        columns: u8,
+        image_size: (usize, usize),
+        image_mode: ImageMode,
+        is_v2: bool,
+        input_report_size: usize,
+    }
+
+    const VECTORS: &[Vector] = &[
+        Vector {
+            pid: 0x0060,
+            product: "Stream Deck",
+            kind: Kind::Original,
+            keys: 15,
+            columns: 5,
+            image_size: (72, 72),
+            image_mode: ImageMode::Bmp,
+            is_v2: false,
+            input_report_size: 16,
+        },
+        Vector {
+            pid: 0x0063,
+            product: "Stream Deck Mini",
+            kind: Kind::Mini,
+            keys: 6,
+            columns: 3,
+            image_size: (80, 80),
+            image_mode: ImageMode::Bmp,
+            is_v2: false,
+            input_report_size: 7,
+        },
+        Vector {
+            pid: 0x006d,
+            product: "Stream Deck Original",
+            kind: Kind::OriginalV2,
+            keys: 15,
+            columns: 5,
+            image_size: (72, 72),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 19,
+        },
+        Vector {
+            pid: 0x006c,
+            product: "Stream Deck XL",
+            kind: Kind::Xl,
+            keys: 32,
+            columns: 8,
+            image_size: (96, 96),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 36,
+        },
+        Vector {
+            pid: 0x008f,
+            product: "Stream Deck XL",
+            kind: Kind::Xl,
+            keys: 32,
+            columns: 8,
+            image_size: (96, 96),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 36,
+        },
+        Vector {
+            pid: 0x0080,
+            product: "Stream Deck MK.2",
+            kind: Kind::Mk2,
+            keys: 15,
+            columns: 5,
+            image_size: (72, 72),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 19,
+        },
+        Vector {
+            pid: 0x00a5,
+            product: "Stream Deck MK.2",
+            kind: Kind::Mk2,
+            keys: 15,
+            columns: 5,
+            image_size: (72, 72),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 19,
+        },
+        Vector {
+            pid: 0x0090,
+            product: "Stream Deck Mini",
+            kind: Kind::RevisedMini,
+            keys: 6,
+            columns: 3,
+            image_size: (80, 80),
+            image_mode: ImageMode::Bmp,
+            is_v2: false,
+            input_report_size: 7,
+        },
+        Vector {
+            pid: 0x00b3,
+            product: "Stream Deck Mini",
+            kind: Kind::RevisedMini,
+            keys: 6,
+            columns: 3,
+            image_size: (80, 80),
+            image_mode: ImageMode::Bmp,
+            is_v2: false,
+            input_report_size: 7,
+        },
+        Vector {
+            pid: 0x0086,
+            product: "Stream Deck Pedal",
+            kind: Kind::Pedal,
+            keys: 3,
+            columns: 3,
+            image_size: (0, 0),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 7,
+        },
+        Vector {
+            pid: 0x009a,
+            product: "Stream Deck Neo",
+            kind: Kind::Neo,
+            keys: 8,
+            columns: 4,
+            image_size: (96, 96),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 14,
+        },
+        Vector {
+            pid: 0x00aa,
+            product: "Stream Deck Studio",
+            kind: Kind::Studio,
+            keys: 32,
+            columns: 16,
+            image_size: (144, 112),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 36,
+        },
+        Vector {
+            pid: 0x00b8,
+            product: "Stream Deck Module 6",
+            kind: Kind::Module6,
+            keys: 6,
+            columns: 3,
+            image_size: (80, 80),
+            image_mode: ImageMode::Bmp,
+            is_v2: false,
+            input_report_size: 7,
+        },
+        Vector {
+            pid: 0x00b9,
+            product: "Stream Deck Module 15",
+            kind: Kind::Module15,
+            keys: 15,
+            columns: 5,
+            image_size: (72, 72),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 19,
+        },
+        Vector {
+            pid: 0x00ba,
+            product: "Stream Deck Module 32",
+            kind: Kind::Module32,
+            keys: 32,
+            columns: 8,
+            image_size: (96, 96),
+            image_mode: ImageMode::Jpeg,
+            is_v2: true,
+            input_report_size: 36,
+        },
+    ];
+
+    #[test]
+    fn kind_from_pid() {
+        for v in VECTORS {
+            assert_eq!(Kind::from_pid(v.pid), Some(v.kind), "{}", v.product);
+        }
+
+        assert_eq!(Kind::from_pid(0x0000), None);
+    }
+
+    #[test]
+    fn kind_image_formats() {
+        for v in VECTORS {
+            let formats = v.kind.image_formats();
+            if !v.kind.has_display() {
+                assert!(formats.is_empty(), "{}", v.product);
+                continue;
+            }
+            assert!(!formats.is_empty(), "{}", v.product);
+            assert!(v.kind.supports_image_format(&v.image_mode), "{}", v.product);
+            assert_eq!(formats[0], v.kind.image_mode(), "{}", v.product);
+        }
+
+        assert!(!Kind::Original.supports_image_format(&ImageMode::Jpeg));
+        assert!(!Kind::Xl.supports_image_format(&ImageMode::Bmp));
+    }
+
+    #[test]
+    fn capability_summary() {
+        assert_eq!(
+            Kind::Xl.capabilities().to_string(),
+            "32 keys (8x4), 96x96 Jpeg images, dials: 0"
+        );
+        assert_eq!(
+            Kind::Pedal.capabilities().to_string(),
+            "3 keys (3x1), no display, dials: 0"
+        );
+        assert_eq!(
+            Kind::Studio.capabilities().to_string(),
+            "32 keys (16x2), 144x112 Jpeg images, dials: 2"
+        );
+    }
+
+    #[test]
+    fn firmware_versions() {
+        let v = FirmwareVersion::parse("1.00.006");
+        assert_eq!((v.major, v.minor, v.patch), (1, 0, 6));
+        assert!(v.is_parsed());
+
+        let v = FirmwareVersion::parse("1.0.170133");
+        assert_eq!((v.major, v.minor, v.patch), (1, 0, 170133));
+
+        let v = FirmwareVersion::parse("2.05");
+        assert_eq!((v.major, v.minor, v.patch), (2, 5, 0));
+
+        let v = FirmwareVersion::parse("AP2 1.0");
+        assert!(!v.is_parsed());
+        assert_eq!(v.raw, "AP2 1.0");
+
+        let mut versions: Vec<_> = ["3.00.000", "1.01.000", "1.00.006"]
+            .iter()
+            .map(|v| FirmwareVersion::parse(v))
+            .collect();
+        versions.sort();
+        assert_eq!(
+            versions.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+            vec!["1.00.006", "1.01.000", "3.00.000"]
+        );
+    }
+
+    #[test]
+    fn kind_brightness() {
+        for v in VECTORS {
+            let (min, max) = v.kind.brightness_range();
+            assert_eq!(v.kind.brightness_raw(0), min, "{}", v.product);
+            assert_eq!(v.kind.brightness_raw(100), max, "{}", v.product);
+            assert_eq!(v.kind.brightness_raw(200), max, "{}", v.product);
+
+            // Models without normalisation data pass through unchanged
+            if (min, max) == (0, 100) {
+                for p in 0..=100 {
+                    assert_eq!(v.kind.brightness_raw(p), p, "{}", v.product);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn kind_capabilities() {
+        for v in VECTORS {
+            let k = v.kind;
+
+            assert_eq!(k.name(), v.product);
+            assert_eq!(k.keys(), v.keys, "{}", v.product);
+            assert_eq!(k.key_columns(), v.columns, "{}", v.product);
+            assert_eq!(k.keys() % k.key_columns(), 0, "{}", v.product);
+            assert_eq!(k.image_size(), v.image_size, "{}", v.product);
+            assert_eq!(k.image_mode(), v.image_mode, "{}", v.product);
+            assert_eq!(k.is_v2(), v.is_v2, "{}", v.product);
+
+            // BMP rows are not padded, so must already be aligned to 4 bytes
+            if v.image_mode == ImageMode::Bmp {
+                assert_eq!(k.image_size().0 * 3 % 4, 0, "{}", v.product);
+            }
+
+            let c = k.capabilities();
+            assert_eq!(
+                (c.keys, c.columns, c.rows),
+                (v.keys, v.columns, v.keys / v.columns),
+                "{}",
+                v.product
+            );
+            assert_eq!(c.image_size, v.image_size, "{}", v.product);
+            assert_eq!(c.image_mode, v.image_mode, "{}", v.product);
+            assert_eq!(c.dials, k.dials(), "{}", v.product);
+            assert_eq!(k.input_report_size(), v.input_report_size, "{}", v.product);
+            assert_eq!(
+                k.key_image_bytes_len(),
+                v.image_size.0 * v.image_size.1 * 3,
+                "{}",
+                v.product
+            );
+        }
+    }
+}
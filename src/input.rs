@@ -0,0 +1,1234 @@
+//! Event based input handling
+//!
+//! [InputManager] polls the device and converts changes in button state into [InputEvent]s.
+//! [InputState] may be used directly where the device must remain available between reads,
+//! with button states from [StreamDeck::read_buttons_raw]. [InputListener] reads a shared
+//! device from a background thread, leaving it available for writes from other threads.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{Error, SharedStreamDeck, StreamDeck};
+
+/// Button state transitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ButtonAction {
+    Pressed,
+    Released,
+}
+
+/// Dial state transitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DialAction {
+    Pressed,
+    Released,
+    /// The dial was turned by the provided number of steps, positive clockwise
+    Turned(i8),
+    /// The dial was pressed and released, flipping its toggle state to the provided value,
+    /// for dials with toggling enabled (see [InputManager::set_dial_toggle])
+    Toggled(bool),
+}
+
+/// Value adjusted by dial turns, kept within a range
+///
+/// Apply the steps from each [DialAction::Turned] with [DialValue::apply]. By default the
+/// value is clamped to the range, with [DialValue::wrapping] it wraps around to the other end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DialValue {
+    pub value: i32,
+    pub min: i32,
+    pub max: i32,
+    /// Change in value per step turned
+    pub step: i32,
+    /// Wrap around at the ends of the range rather than clamping
+    pub wrap: bool,
+}
+
+impl DialValue {
+    /// Create a clamped value within `min..=max`, the initial value is clamped to the range
+    ///
+    /// Panics if `min` is greater than `max`.
+    pub fn new(value: i32, min: i32, max: i32, step: i32) -> Self {
+        assert!(min <= max, "dial value minimum exceeds maximum");
+
+        Self {
+            value: value.clamp(min, max),
+            min,
+            max,
+            step,
+            wrap: false,
+        }
+    }
+
+    /// Wrap around at the ends of the range rather than clamping
+    pub fn wrapping(self) -> Self {
+        Self { wrap: true, ..self }
+    }
+
+    /// Adjust the value by the provided number of steps, returning the new value
+    pub fn apply(&mut self, delta: i8) -> i32 {
+        let (min, max) = (self.min as i64, self.max as i64);
+        let value = self.value as i64 + delta as i64 * self.step as i64;
+
+        let value = match self.wrap {
+            true => min + (value - min).rem_euclid(max - min + 1),
+            false => value.clamp(min, max),
+        };
+
+        self.value = value as i32;
+        self.value
+    }
+}
+
+/// Input events
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InputEvent {
+    /// A button was pressed or released
+    Button { index: u8, action: ButtonAction },
+    /// A dial was pressed, released or turned, for devices with dials (see [Kind::dials](crate::Kind::dials))
+    Dial { index: u8, action: DialAction },
+    /// No input has occurred for the configured idle timeout
+    Idle,
+    /// All of the keys of a registered chord are now held
+    Chord(BTreeSet<u8>),
+}
+
+/// Key indexing used for button events, see [InputManager::set_index_mode]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexMode {
+    /// Keys are indexed in the same order as images
+    #[default]
+    Logical,
+    /// Keys are indexed by their position in input reports, see [StreamDeck::physical_key_index]
+    Physical,
+}
+
+/// Decoded input report, see [StreamDeck::read_input_report]
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputReport {
+    /// Button states, as returned by [StreamDeck::read_buttons_raw]
+    Buttons(Vec<u8>),
+    /// Dial input, for devices with dials
+    Dials(DialInput),
+}
+
+/// Dial input from a single report
+#[derive(Debug, Clone, PartialEq)]
+pub enum DialInput {
+    /// Pressed state of each dial
+    Pressed(Vec<bool>),
+    /// Steps each dial was turned since the last report
+    Turned(Vec<i8>),
+}
+
+/// Button states from a single input report, see [StreamDeck::read_buttons]
+///
+/// Keys are indexed in the same order as images, with the device key direction
+/// already applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ButtonStates {
+    states: Vec<u8>,
+}
+
+impl ButtonStates {
+    /// Create button states from per-key values, where non-zero values are pressed
+    pub fn new(states: Vec<u8>) -> Self {
+        Self { states }
+    }
+
+    /// Check whether a key is pressed, keys out of range are not pressed
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.states.get(key as usize).is_some_and(|s| *s != 0)
+    }
+
+    /// Iterate over the indices of pressed keys
+    pub fn pressed_indices(&self) -> impl Iterator<Item = u8> + '_ {
+        self.states
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| **s != 0)
+            .map(|(i, _)| i as u8)
+    }
+
+    /// Fetch the number of pressed keys
+    pub fn count(&self) -> usize {
+        self.pressed_indices().count()
+    }
+
+    /// Fetch the number of keys
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Check whether there are no keys
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Fetch the raw per-key values
+    pub fn as_raw(&self) -> &[u8] {
+        &self.states
+    }
+}
+
+impl From<ButtonStates> for Vec<u8> {
+    fn from(b: ButtonStates) -> Self {
+        b.states
+    }
+}
+
+/// Input manager, tracks device state between reads to produce events
+pub struct InputManager<'a> {
+    deck: &'a mut StreamDeck,
+    state: InputState,
+    index_mode: IndexMode,
+    poll_interval: Option<Duration>,
+    last_empty_read: Option<Instant>,
+    drain_queued: bool,
+    /// Error from a follow-up read while draining, returned by the next read
+    drain_error: Option<Error>,
+    button_callbacks: Vec<Box<dyn FnMut(u8, ButtonAction) + 'a>>,
+    dial_callbacks: Vec<Box<dyn FnMut(u8, DialAction) + 'a>>,
+}
+
+impl<'a> InputManager<'a> {
+    /// Create a new input manager for the provided device
+    pub fn new(deck: &'a mut StreamDeck) -> Self {
+        let state = InputState::new(deck.kind().buttons(), Instant::now());
+        Self::with_state(deck, state)
+    }
+
+    /// Create a new input manager for the provided device, continuing from existing state
+    pub fn with_state(deck: &'a mut StreamDeck, state: InputState) -> Self {
+        Self {
+            deck,
+            state,
+            index_mode: IndexMode::default(),
+            poll_interval: None,
+            last_empty_read: None,
+            drain_queued: false,
+            drain_error: None,
+            button_callbacks: Vec::new(),
+            dial_callbacks: Vec::new(),
+        }
+    }
+
+    /// Release the device, returning the input state for later use
+    pub fn into_state(self) -> InputState {
+        self.state
+    }
+
+    /// Set the key indexing used for button events and callbacks, [IndexMode::Logical] by default
+    ///
+    /// Chords are always registered and reported with logical indices.
+    pub fn set_index_mode(&mut self, mode: IndexMode) {
+        self.index_mode = mode;
+    }
+
+    /// Set a minimum interval between reads following a read that returned no data,
+    /// disabled (`None`) by default
+    ///
+    /// Polling with a zero timeout, or in non-blocking mode, otherwise spins a CPU core while
+    /// no input is available. With an interval set the next read sleeps for the remainder of the
+    /// interval after an empty read, adding up to the interval to the latency of the next input.
+    /// Reads following input are not delayed.
+    pub fn set_poll_interval(&mut self, interval: Option<Duration>) {
+        self.poll_interval = interval;
+    }
+
+    /// Enable or disable draining queued reports, disabled by default
+    ///
+    /// When enabled, each read is followed by zero timeout reads until no further reports
+    /// are available, so a burst of input queued by the device is returned from a single
+    /// call rather than one report per call. Events are returned in the order the reports
+    /// were received. An error on a follow-up read ends the drain, with the events read
+    /// so far returned and the error left to be reported by the next read.
+    pub fn set_drain_queued(&mut self, drain: bool) {
+        self.drain_queued = drain;
+    }
+
+    /// See [InputState::set_idle_timeout]
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.state.set_idle_timeout(timeout);
+    }
+
+    /// See [InputState::add_chord]
+    pub fn add_chord(&mut self, keys: &[u8]) -> Result<(), Error> {
+        self.state.add_chord(keys)
+    }
+
+    /// Fetch the set of currently pressed keys
+    pub fn pressed_chord(&self) -> BTreeSet<u8> {
+        self.state.pressed_chord()
+    }
+
+    /// See [InputState::set_dial_toggle]
+    ///
+    /// Returns [Error::InvalidDialIndex] if the dial does not exist on the device.
+    pub fn set_dial_toggle(&mut self, dial: u8, enabled: bool) -> Result<(), Error> {
+        if dial >= self.deck.dials() {
+            return Err(Error::InvalidDialIndex);
+        }
+
+        self.state.set_dial_toggle(dial, enabled);
+        Ok(())
+    }
+
+    /// See [InputState::dial_toggle]
+    pub fn dial_toggle(&self, dial: u8) -> Option<bool> {
+        self.state.dial_toggle(dial)
+    }
+
+    /// Register a callback to be invoked for each button event
+    ///
+    /// Callbacks are run from [InputManager::handle_input] on the polling thread, in order of
+    /// registration, for each event in the order they are returned.
+    pub fn on_button(&mut self, callback: Box<dyn FnMut(u8, ButtonAction) + 'a>) {
+        self.button_callbacks.push(callback);
+    }
+
+    /// Register a callback to be invoked for each dial event, for devices with dials
+    ///
+    /// Callbacks are run as with [InputManager::on_button], interleaved with button
+    /// callbacks in the order events are returned.
+    pub fn on_dial(&mut self, callback: Box<dyn FnMut(u8, DialAction) + 'a>) {
+        self.dial_callbacks.push(callback);
+    }
+
+    /// Read from the device and return any resulting input events,
+    /// invoking any registered callbacks before returning
+    ///
+    /// See [StreamDeck::read_buttons] for the timeout and blocking behaviour,
+    /// reads that time out without data return no events rather than an error.
+    pub fn handle_input(&mut self, timeout: Option<Duration>) -> Result<Vec<InputEvent>, Error> {
+        self.handle_input_with_raw(timeout)
+            .map(|(events, _)| events)
+    }
+
+    /// Read from the device as [InputManager::handle_input], also returning the raw input
+    /// report (including the report ID) the events were decoded from
+    ///
+    /// The report is empty where the read timed out without data, and is the last report
+    /// read where queued reports are drained (see [InputManager::set_drain_queued]).
+    pub fn handle_input_with_raw(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<(Vec<InputEvent>, Vec<u8>), Error> {
+        if let Some(e) = self.drain_error.take() {
+            return Err(e);
+        }
+
+        if let (Some(interval), Some(last)) = (self.poll_interval, self.last_empty_read) {
+            std::thread::sleep(interval.saturating_sub(last.elapsed()));
+        }
+
+        let (report, mut raw) = match self.deck.read_input_report_raw(timeout) {
+            Ok((report, r)) => {
+                self.last_empty_read = None;
+                (Some(report), r)
+            }
+            Err(Error::NoData) => {
+                self.last_empty_read = Some(Instant::now());
+                (None, Vec::new())
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut events = self.state.update_report(report.as_ref(), Instant::now());
+
+        if self.drain_queued && report.is_some() {
+            loop {
+                match self.deck.read_input_report_raw(Some(Duration::ZERO)) {
+                    Ok((report, r)) => {
+                        events.extend(self.state.update_report(Some(&report), Instant::now()));
+                        raw = r;
+                    }
+                    Err(Error::NoData) => break,
+                    Err(e) => {
+                        self.drain_error = Some(e);
+                        break;
+                    }
+                }
+            }
+        }
+
+        if self.index_mode == IndexMode::Physical {
+            for event in events.iter_mut() {
+                if let InputEvent::Button { index, .. } = event {
+                    *index = self.deck.physical_key_index(*index)?;
+                }
+            }
+        }
+
+        for event in &events {
+            match event {
+                InputEvent::Button { index, action } => {
+                    for cb in self.button_callbacks.iter_mut() {
+                        cb(*index, *action);
+                    }
+                }
+                InputEvent::Dial { index, action } => {
+                    for cb in self.dial_callbacks.iter_mut() {
+                        cb(*index, *action);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok((events, raw))
+    }
+
+    /// Repeatedly read input, passing each batch of events to the provided closure
+    ///
+    /// The closure is called after every read, with an empty batch where a read times out,
+    /// and the loop exits once this returns [ControlFlow::Break] or when a device error occurs.
+    /// See [ReconnectingStreamDeck::run](crate::ReconnectingStreamDeck::run) to reconnect on
+    /// device errors instead.
+    pub fn run<F>(&mut self, timeout: Option<Duration>, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(Vec<InputEvent>) -> ControlFlow<()>,
+    {
+        loop {
+            let events = self.handle_input(timeout)?;
+
+            if let ControlFlow::Break(()) = f(events) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Background input reader, delivering events from a shared device over a channel
+///
+/// Each read locks the device for at most the read timeout, so writes from other threads
+/// through the [SharedStreamDeck] are delayed by up to the timeout. The thread exits when
+/// the listener is stopped or dropped, or on a device error, which is returned by
+/// [InputListener::stop]. A panic on the reader thread is resumed by
+/// [InputListener::stop], and only logged when the listener is dropped.
+pub struct InputListener {
+    events: Receiver<InputEvent>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl InputListener {
+    /// Start reading from the provided device, with each read waiting up to `timeout`
+    pub fn new(deck: SharedStreamDeck, timeout: Duration) -> Self {
+        let state = InputState::new(deck.lock().kind().buttons(), Instant::now());
+        Self::with_state(deck, state, timeout)
+    }
+
+    /// Start reading from the provided device, continuing from existing state
+    ///
+    /// This allows idle timeouts and chords to be configured before starting.
+    pub fn with_state(deck: SharedStreamDeck, state: InputState, timeout: Duration) -> Self {
+        let (tx, events) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let s = stop.clone();
+        let handle = std::thread::spawn(move || {
+            // The receiver is held by the listener, so is only dropped once stopping
+            listen(deck, state, timeout, &s, |e| {
+                let _ = tx.send(e);
+            })
+        });
+
+        Self {
+            events,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Fetch the receiver for input events
+    ///
+    /// The receiver is disconnected once the reader thread has exited.
+    pub fn events(&self) -> &Receiver<InputEvent> {
+        &self.events
+    }
+
+    /// Stop the reader thread, returning any device error that ended it
+    ///
+    /// If the reader thread panicked, the panic is resumed on the calling thread.
+    pub fn stop(mut self) -> Result<(), Error> {
+        match self.join() {
+            Ok(r) => r,
+            Err(p) => std::panic::resume_unwind(p),
+        }
+    }
+
+    fn join(&mut self) -> std::thread::Result<Result<(), Error>> {
+        self.stop.store(true, Ordering::Relaxed);
+
+        match self.handle.take() {
+            Some(h) => h.join(),
+            None => Ok(Ok(())),
+        }
+    }
+}
+
+impl Drop for InputListener {
+    fn drop(&mut self) {
+        // Resuming a panic here would abort if the listener is dropped during unwinding
+        match self.join() {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => debug!("Input listener exited with error: {}", e),
+            Err(p) => {
+                let msg = p
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| p.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                warn!("Input listener thread panicked: {}", msg);
+            }
+        }
+    }
+}
+
+/// Read input from a shared device until stopped, passing events to the provided closure
+pub(crate) fn listen<F: FnMut(InputEvent)>(
+    deck: SharedStreamDeck,
+    mut state: InputState,
+    timeout: Duration,
+    stop: &AtomicBool,
+    mut emit: F,
+) -> Result<(), Error> {
+    while !stop.load(Ordering::Relaxed) {
+        let report = match deck.read_input_report(Some(timeout)) {
+            Ok(report) => Some(report),
+            Err(Error::NoData) => None,
+            Err(e) => return Err(e),
+        };
+
+        for event in state.update_report(report.as_ref(), Instant::now()) {
+            emit(event);
+        }
+    }
+
+    Ok(())
+}
+
+/// Device independent input state, tracked between reads to produce events
+///
+/// This is used by [InputManager], and may be used directly so the device is not
+/// borrowed between reads, by passing the results of [StreamDeck::read_input_report]
+/// to [InputState::update_report] (or of [StreamDeck::read_buttons_raw] to [InputState::update]).
+pub struct InputState {
+    pressed: Vec<bool>,
+    /// Pressed state of each dial, sized by the first dial report
+    dials: Vec<bool>,
+    /// Toggle state of dials with toggling enabled
+    toggles: BTreeMap<u8, bool>,
+    /// Registered chords and whether each is currently active
+    chords: Vec<(BTreeSet<u8>, bool)>,
+    idle_timeout: Option<Duration>,
+    last_input: Instant,
+    idle: bool,
+}
+
+impl InputState {
+    /// Create input state for a device with the provided number of buttons
+    /// (see [Kind::buttons](crate::Kind::buttons)), with the idle timer starting at `now`
+    pub fn new(keys: u8, now: Instant) -> Self {
+        Self {
+            pressed: vec![false; keys as usize],
+            dials: Vec::new(),
+            toggles: BTreeMap::new(),
+            chords: Vec::new(),
+            idle_timeout: None,
+            last_input: now,
+            idle: false,
+        }
+    }
+
+    /// Set the duration without input after which an [InputEvent::Idle] is emitted
+    ///
+    /// As events are only produced while reading, the idle event is emitted by the first
+    /// update after the timeout has elapsed, and is emitted once per idle period.
+    /// Any button or dial input restarts the timer. Disabled (`None`) by default.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    /// Register a chord (set of keys), emitting an [InputEvent::Chord] when all the keys are held
+    ///
+    /// The chord event follows the button event for the last key pressed. Releasing any key
+    /// of the chord cancels it, so it is emitted again once all of the keys are next held.
+    pub fn add_chord(&mut self, keys: &[u8]) -> Result<(), Error> {
+        if keys.is_empty() || keys.iter().any(|k| *k as usize >= self.pressed.len()) {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        self.chords.push((keys.iter().copied().collect(), false));
+
+        Ok(())
+    }
+
+    /// Enable or disable toggling for a dial, disabled by default
+    ///
+    /// Each time a dial with toggling enabled is pressed and released, its toggle state
+    /// flips and a [DialAction::Toggled] event follows the release. The state starts off
+    /// (`false`) and is reset when toggling is disabled.
+    pub fn set_dial_toggle(&mut self, dial: u8, enabled: bool) {
+        match enabled {
+            true => {
+                self.toggles.entry(dial).or_insert(false);
+            }
+            false => {
+                self.toggles.remove(&dial);
+            }
+        }
+    }
+
+    /// Fetch the toggle state of a dial, or `None` where toggling is not enabled
+    pub fn dial_toggle(&self, dial: u8) -> Option<bool> {
+        self.toggles.get(&dial).copied()
+    }
+
+    /// Update state with the latest button states (if any were read), returning resulting events
+    ///
+    /// Button states are as returned by [StreamDeck::read_buttons_raw], with `None`
+    /// where no report was read so idle timeouts may still be emitted.
+    pub fn update(&mut self, buttons: Option<&[u8]>, now: Instant) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        for (i, (state, value)) in self
+            .pressed
+            .iter_mut()
+            .zip(buttons.unwrap_or_default())
+            .enumerate()
+        {
+            let pressed = *value != 0;
+            // Keys start released, so a key is only reported released after a press
+            // has been seen (including keys already held on the first report)
+            if pressed == *state {
+                continue;
+            }
+
+            *state = pressed;
+            events.push(InputEvent::Button {
+                index: i as u8,
+                action: if pressed {
+                    ButtonAction::Pressed
+                } else {
+                    ButtonAction::Released
+                },
+            });
+        }
+
+        if !events.is_empty() {
+            let pressed = self.pressed_chord();
+            for (chord, active) in self.chords.iter_mut() {
+                let held = chord.is_subset(&pressed);
+                if held && !*active {
+                    events.push(InputEvent::Chord(chord.clone()));
+                }
+                *active = held;
+            }
+        }
+
+        self.update_idle(&mut events, now);
+        events
+    }
+
+    /// Update state with a decoded input report (if any was read), returning resulting events
+    ///
+    /// Reports are as returned by [StreamDeck::read_input_report], with `None` where no
+    /// report was read so idle timeouts may still be emitted.
+    pub fn update_report(&mut self, report: Option<&InputReport>, now: Instant) -> Vec<InputEvent> {
+        match report {
+            Some(InputReport::Buttons(buttons)) => self.update(Some(buttons), now),
+            Some(InputReport::Dials(input)) => self.update_dials(input, now),
+            None => self.update(None, now),
+        }
+    }
+
+    /// Update state with dial input, returning resulting events
+    ///
+    /// Dial states are sized by the first press report, turns are reported as they are read.
+    pub fn update_dials(&mut self, input: &DialInput, now: Instant) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        match input {
+            DialInput::Pressed(pressed) => {
+                self.dials.resize(pressed.len(), false);
+
+                for (i, (state, pressed)) in self.dials.iter_mut().zip(pressed).enumerate() {
+                    if *pressed == *state {
+                        continue;
+                    }
+
+                    *state = *pressed;
+                    events.push(InputEvent::Dial {
+                        index: i as u8,
+                        action: if *pressed {
+                            DialAction::Pressed
+                        } else {
+                            DialAction::Released
+                        },
+                    });
+
+                    if let (false, Some(toggle)) = (*pressed, self.toggles.get_mut(&(i as u8))) {
+                        *toggle = !*toggle;
+                        events.push(InputEvent::Dial {
+                            index: i as u8,
+                            action: DialAction::Toggled(*toggle),
+                        });
+                    }
+                }
+            }
+            DialInput::Turned(steps) => {
+                for (i, steps) in steps.iter().enumerate().filter(|(_, s)| **s != 0) {
+                    events.push(InputEvent::Dial {
+                        index: i as u8,
+                        action: DialAction::Turned(*steps),
+                    });
+                }
+            }
+        }
+
+        self.update_idle(&mut events, now);
+        events
+    }
+
+    /// Restart the idle timer where there are input events, otherwise emit an
+    /// [InputEvent::Idle] once the timeout has elapsed
+    fn update_idle(&mut self, events: &mut Vec<InputEvent>, now: Instant) {
+        if !events.is_empty() {
+            self.last_input = now;
+            self.idle = false;
+        } else if let Some(timeout) = self.idle_timeout {
+            if !self.idle && now.duration_since(self.last_input) >= timeout {
+                self.idle = true;
+                events.push(InputEvent::Idle);
+            }
+        }
+    }
+
+    /// Fetch the set of currently pressed keys
+    pub fn pressed_chord(&self) -> BTreeSet<u8> {
+        self.pressed
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| **p)
+            .map(|(i, _)| i as u8)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Kind, MockTransport};
+    use std::cell::RefCell;
+
+    #[test]
+    fn button_callbacks() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Mini);
+
+        let calls = RefCell::new(Vec::new());
+        let mut manager = InputManager::new(&mut deck);
+        manager.on_button(Box::new(|i, a| calls.borrow_mut().push((1, i, a))));
+        manager.on_button(Box::new(|i, a| calls.borrow_mut().push((2, i, a))));
+
+        mock.push_input(&[0x01, 0, 1, 1, 0, 0, 0]);
+        mock.push_input(&[0x01, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(manager.handle_input(None).unwrap().len(), 2);
+        assert_eq!(manager.handle_input(None).unwrap().len(), 1);
+        // No data available
+        assert!(manager.handle_input(None).unwrap().is_empty());
+        drop(manager);
+
+        let calls: Vec<_> = calls
+            .into_inner()
+            .into_iter()
+            .map(|(cb, i, a)| (cb, i, matches!(a, ButtonAction::Pressed)))
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                (1, 1, true),
+                (2, 1, true),
+                (1, 2, true),
+                (2, 2, true),
+                (1, 1, false),
+                (2, 1, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn dial_callbacks() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Studio);
+
+        let calls = RefCell::new(Vec::new());
+        let mut manager = InputManager::new(&mut deck);
+        manager.on_dial(Box::new(|i, a| calls.borrow_mut().push((i, a))));
+        manager.on_button(Box::new(|_, _| panic!("unexpected button event")));
+
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x00, 0x01, 0x00]);
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x01, 0x00, 0xff]);
+        assert_eq!(manager.handle_input(None).unwrap().len(), 1);
+        assert_eq!(manager.handle_input(None).unwrap().len(), 1);
+        drop(manager);
+
+        assert_eq!(
+            calls.into_inner(),
+            vec![(0, DialAction::Pressed), (1, DialAction::Turned(-1))]
+        );
+    }
+
+    #[test]
+    fn physical_index_mode() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Original);
+
+        // First key in the report, the top right key on the original
+        let mut report = vec![0u8; Kind::Original.input_report_size()];
+        report[0] = 0x01;
+        report[1] = 1;
+
+        let mut manager = InputManager::new(&mut deck);
+        mock.push_input(&report);
+        assert_eq!(
+            manager.handle_input(None).unwrap(),
+            vec![InputEvent::Button {
+                index: 4,
+                action: ButtonAction::Pressed
+            }]
+        );
+
+        manager.set_index_mode(IndexMode::Physical);
+        report[1] = 0;
+        mock.push_input(&report);
+        assert_eq!(
+            manager.handle_input(None).unwrap(),
+            vec![InputEvent::Button {
+                index: 0,
+                action: ButtonAction::Released
+            }]
+        );
+    }
+
+    #[test]
+    fn raw_reports() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Mini);
+        let mut manager = InputManager::new(&mut deck);
+
+        mock.push_input(&[0x01, 0, 1, 0, 0, 0, 0]);
+        let (events, raw) = manager.handle_input_with_raw(None).unwrap();
+        assert_eq!(raw, vec![0x01, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(
+            events,
+            vec![InputEvent::Button {
+                index: 1,
+                action: ButtonAction::Pressed
+            }]
+        );
+
+        assert_eq!(
+            manager.handle_input_with_raw(None).unwrap(),
+            (vec![], vec![])
+        );
+    }
+
+    #[test]
+    fn drain_queued_reports() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Mini);
+        let mut manager = InputManager::new(&mut deck);
+        manager.set_drain_queued(true);
+
+        // A press and release of the same key, then another press, in a single burst
+        mock.push_input(&[0x01, 1, 0, 0, 0, 0, 0]);
+        mock.push_input(&[0x01, 0, 0, 0, 0, 0, 0]);
+        mock.push_input(&[0x01, 0, 0, 1, 0, 0, 0]);
+
+        let event = |index, action| InputEvent::Button { index, action };
+        let (events, raw) = manager.handle_input_with_raw(None).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                event(0, ButtonAction::Pressed),
+                event(0, ButtonAction::Released),
+                event(2, ButtonAction::Pressed),
+            ]
+        );
+        assert_eq!(raw, vec![0x01, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(manager.pressed_chord(), BTreeSet::from([2]));
+
+        // State carries over to later reads
+        mock.push_input(&[0x01, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            manager.handle_input(None).unwrap(),
+            vec![event(2, ButtonAction::Released)]
+        );
+        assert!(manager.handle_input(None).unwrap().is_empty());
+
+        // Errors while draining are returned by the next read, after the events read so far
+        mock.push_input(&[0x01, 1, 0, 0, 0, 0, 0]);
+        mock.push_input(&[0x01, 0x02]);
+        mock.push_input(&[0x01, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            manager.handle_input(None).unwrap(),
+            vec![event(0, ButtonAction::Pressed)]
+        );
+        assert!(matches!(
+            manager.handle_input(None),
+            Err(Error::InvalidReport)
+        ));
+        assert_eq!(
+            manager.handle_input(None).unwrap(),
+            vec![event(0, ButtonAction::Released)]
+        );
+    }
+
+    #[test]
+    fn poll_interval() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Mini);
+        let mut manager = InputManager::new(&mut deck);
+
+        let interval = Duration::from_millis(50);
+        manager.set_poll_interval(Some(interval));
+
+        // The first read is not delayed, the read following an empty read is
+        let start = Instant::now();
+        assert!(manager
+            .handle_input(Some(Duration::ZERO))
+            .unwrap()
+            .is_empty());
+        assert!(start.elapsed() < interval);
+
+        mock.push_input(&[0x01, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(manager.handle_input(Some(Duration::ZERO)).unwrap().len(), 1);
+        assert!(start.elapsed() >= interval);
+
+        // Reads following input are not delayed
+        let start = Instant::now();
+        assert!(manager
+            .handle_input(Some(Duration::ZERO))
+            .unwrap()
+            .is_empty());
+        assert!(start.elapsed() < interval);
+    }
+
+    #[test]
+    fn run_until_break() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Mini);
+
+        mock.push_input(&[0x01, 1, 0, 0, 0, 0, 0]);
+        mock.push_input(&[0x01, 0, 0, 0, 0, 0, 0]);
+
+        let mut batches = Vec::new();
+        let mut manager = InputManager::new(&mut deck);
+        manager
+            .run(None, |events| {
+                // Stop once reads run out of data
+                let empty = events.is_empty();
+                batches.push(events);
+                match empty {
+                    true => ControlFlow::Break(()),
+                    false => ControlFlow::Continue(()),
+                }
+            })
+            .unwrap();
+
+        assert_eq!(batches.len(), 3);
+        assert_eq!(
+            batches[1],
+            vec![InputEvent::Button {
+                index: 0,
+                action: ButtonAction::Released
+            }]
+        );
+    }
+
+    #[test]
+    fn background_listener() {
+        let mock = MockTransport::new();
+        let deck = SharedStreamDeck::new(StreamDeck::with_transport(mock.clone(), Kind::Mini));
+
+        let listener = InputListener::new(deck.clone(), Duration::from_millis(1));
+        mock.push_input(&[0x01, 0, 0, 0, 1, 0, 0]);
+        mock.push_input(&[0x01, 0, 0, 0, 0, 0, 0]);
+
+        // Writes are not blocked by the listener
+        deck.set_button_rgb(3, crate::Colour::RED).unwrap();
+
+        let recv = || listener.events().recv_timeout(Duration::from_secs(5));
+        assert_eq!(
+            recv().unwrap(),
+            InputEvent::Button {
+                index: 3,
+                action: ButtonAction::Pressed
+            }
+        );
+        assert_eq!(
+            recv().unwrap(),
+            InputEvent::Button {
+                index: 3,
+                action: ButtonAction::Released
+            }
+        );
+        listener.stop().unwrap();
+    }
+
+    #[test]
+    fn listener_panic() {
+        let panicking = || InputListener {
+            events: channel().1,
+            stop: Arc::new(AtomicBool::new(false)),
+            handle: Some(std::thread::spawn(|| panic!("reader failed"))),
+        };
+
+        // Dropping only logs the panic
+        drop(panicking());
+
+        // Stopping explicitly resumes it
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| panicking().stop()));
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn button_states() {
+        let b = ButtonStates::new(vec![0, 1, 0, 1, 0, 0]);
+        assert!(b.is_pressed(1));
+        assert!(!b.is_pressed(0));
+        assert!(!b.is_pressed(6));
+        assert_eq!(b.pressed_indices().collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!((b.count(), b.len()), (2, 6));
+    }
+
+    #[test]
+    fn event_equality() {
+        use std::collections::HashSet;
+
+        let pressed = InputEvent::Button {
+            index: 1,
+            action: ButtonAction::Pressed,
+        };
+        let events = [
+            pressed.clone(),
+            InputEvent::Idle,
+            pressed.clone(),
+            InputEvent::Chord(BTreeSet::from([0, 1])),
+            InputEvent::Chord(BTreeSet::from([1, 0])),
+        ];
+
+        let unique: HashSet<_> = events.iter().cloned().collect();
+        assert_eq!(unique.len(), 3);
+        assert_ne!(
+            pressed,
+            InputEvent::Button {
+                index: 1,
+                action: ButtonAction::Released
+            }
+        );
+    }
+
+    #[test]
+    fn button_events() {
+        let now = Instant::now();
+        let mut t = InputState::new(3, now);
+
+        let e = t.update(Some(&[0, 1, 0]), now);
+        assert!(matches!(
+            e[..],
+            [InputEvent::Button {
+                index: 1,
+                action: ButtonAction::Pressed
+            }]
+        ));
+
+        assert!(t.update(Some(&[0, 1, 0]), now).is_empty());
+
+        let e = t.update(Some(&[1, 0, 0]), now);
+        assert!(matches!(
+            e[..],
+            [
+                InputEvent::Button {
+                    index: 0,
+                    action: ButtonAction::Pressed
+                },
+                InputEvent::Button {
+                    index: 1,
+                    action: ButtonAction::Released
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn initial_key_state() {
+        let now = Instant::now();
+
+        // Keys released on the first report are not reported
+        let mut t = InputState::new(3, now);
+        assert!(t.update(Some(&[0, 0, 0]), now).is_empty());
+
+        // Keys held before the first report are reported pressed, then released
+        let mut t = InputState::new(3, now);
+        let e = t.update(Some(&[0, 0, 1]), now);
+        assert!(matches!(
+            e[..],
+            [InputEvent::Button {
+                index: 2,
+                action: ButtonAction::Pressed
+            }]
+        ));
+        let e = t.update(Some(&[0, 0, 0]), now);
+        assert!(matches!(
+            e[..],
+            [InputEvent::Button {
+                index: 2,
+                action: ButtonAction::Released
+            }]
+        ));
+        assert!(t.update(Some(&[0, 0, 0]), now).is_empty());
+    }
+
+    #[test]
+    fn idle_events() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(10);
+        let mut t = InputState::new(3, start);
+
+        // Disabled by default
+        assert!(t.update(None, start + timeout * 2).is_empty());
+
+        t.idle_timeout = Some(timeout);
+        t.last_input = start;
+        assert!(t.update(None, start + timeout / 2).is_empty());
+
+        // Fires once on the first read after the timeout
+        let e = t.update(None, start + timeout);
+        assert!(matches!(e[..], [InputEvent::Idle]));
+        assert!(t.update(None, start + timeout * 2).is_empty());
+
+        // Input resets the timer
+        let pressed = start + timeout * 3;
+        assert_eq!(t.update(Some(&[1, 0, 0]), pressed).len(), 1);
+        assert!(t.update(Some(&[1, 0, 0]), pressed + timeout / 2).is_empty());
+
+        let e = t.update(Some(&[1, 0, 0]), pressed + timeout);
+        assert!(matches!(e[..], [InputEvent::Idle]));
+    }
+
+    #[test]
+    fn chord_events() {
+        let now = Instant::now();
+        let mut t = InputState::new(6, now);
+        let chord = BTreeSet::from([0, 4]);
+        t.chords.push((chord.clone(), false));
+
+        // Partial chord
+        let e = t.update(Some(&[1, 0, 0, 0, 0, 0]), now);
+        assert_eq!(e.len(), 1);
+        assert_eq!(t.pressed_chord(), BTreeSet::from([0]));
+
+        // Completed chord, with unrelated keys held
+        t.update(Some(&[1, 1, 0, 0, 0, 0]), now);
+        let e = t.update(Some(&[1, 1, 0, 0, 1, 0]), now);
+        assert_eq!(e.len(), 2);
+        assert!(matches!(&e[1], InputEvent::Chord(c) if *c == chord));
+
+        // Still held, not re-emitted
+        let e = t.update(Some(&[1, 0, 0, 0, 1, 0]), now);
+        assert_eq!(e.len(), 1);
+
+        // Releasing one key cancels, re-pressing emits again
+        let e = t.update(Some(&[1, 0, 0, 0, 0, 0]), now);
+        assert_eq!(e.len(), 1);
+        let e = t.update(Some(&[1, 0, 0, 0, 1, 0]), now);
+        assert!(matches!(
+            &e[..],
+            [InputEvent::Button { index: 4, .. }, InputEvent::Chord(_)]
+        ));
+    }
+
+    #[test]
+    fn dial_value_clamped() {
+        let mut v = DialValue::new(5, 0, 10, 2);
+        assert_eq!(v.apply(2), 9);
+        assert_eq!(v.apply(1), 10);
+        assert_eq!(v.apply(1), 10);
+        assert_eq!(v.apply(-3), 4);
+        assert_eq!(v.apply(-128), 0);
+        assert_eq!(v.apply(0), 0);
+
+        // Initial values are clamped, and extreme steps do not overflow
+        assert_eq!(DialValue::new(20, 0, 10, 1).value, 10);
+        let mut v = DialValue::new(0, i32::MIN, i32::MAX, i32::MAX);
+        assert_eq!(v.apply(127), i32::MAX);
+        assert_eq!(v.apply(-128), i32::MIN);
+    }
+
+    #[test]
+    fn dial_value_wrapping() {
+        let mut v = DialValue::new(8, 0, 9, 1).wrapping();
+        assert_eq!(v.apply(1), 9);
+        assert_eq!(v.apply(1), 0);
+        assert_eq!(v.apply(-1), 9);
+        assert_eq!(v.apply(25), 4);
+        assert_eq!(v.apply(-15), 9);
+
+        let mut v = DialValue::new(-1, -1, 1, 2).wrapping();
+        assert_eq!(v.apply(1), 1);
+        assert_eq!(v.apply(1), 0);
+
+        let mut v = DialValue::new(0, i32::MIN, i32::MAX, i32::MAX).wrapping();
+        assert_eq!(v.apply(2), -2);
+    }
+
+    #[test]
+    fn dial_toggles() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), Kind::Studio);
+        let mut manager = InputManager::new(&mut deck);
+
+        assert!(matches!(
+            manager.set_dial_toggle(2, true),
+            Err(Error::InvalidDialIndex)
+        ));
+        manager.set_dial_toggle(1, true).unwrap();
+        assert_eq!(manager.dial_toggle(0), None);
+        assert_eq!(manager.dial_toggle(1), Some(false));
+
+        // Three press-release cycles of both dials, only dial 1 toggles
+        let mut toggles = Vec::new();
+        for _ in 0..3 {
+            mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x00, 0x01, 0x01]);
+            mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x00, 0x00, 0x00]);
+
+            assert_eq!(manager.handle_input(None).unwrap().len(), 2);
+            let events = manager.handle_input(None).unwrap();
+            assert_eq!(events.len(), 3);
+            toggles.extend(events.into_iter().filter_map(|e| match e {
+                InputEvent::Dial {
+                    index,
+                    action: DialAction::Toggled(t),
+                } => Some((index, t)),
+                _ => None,
+            }));
+        }
+        assert_eq!(toggles, vec![(1, true), (1, false), (1, true)]);
+        assert_eq!(manager.dial_toggle(1), Some(true));
+
+        // Disabling toggling resets the state
+        manager.set_dial_toggle(1, false).unwrap();
+        assert_eq!(manager.dial_toggle(1), None);
+        manager.set_dial_toggle(1, true).unwrap();
+        assert_eq!(manager.dial_toggle(1), Some(false));
+    }
+}
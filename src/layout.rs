@@ -0,0 +1,165 @@
+//! Panel layouts, describing what is shown on each key for saving and restoring
+
+use crate::{Colour, Error, ImageOptions, StreamDeck};
+
+/// Content set on a key
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum KeyContent {
+    /// Solid colour, set with [StreamDeck::set_button_rgb]
+    Colour(Colour),
+    /// Image file, set with [StreamDeck::set_button_file] or [StreamDeck::set_button_file_default]
+    Image { path: String, options: ImageOptions },
+}
+
+/// Visual state of a device
+///
+/// Exported layouts reflect what was set through this crate since connecting, keys
+/// set from in-memory images, or not set at all, are `None`.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Layout {
+    /// Display brightness in percent, if set
+    pub brightness: Option<u8>,
+    /// Content of each key, indexed by key
+    pub keys: Vec<Option<KeyContent>>,
+}
+
+impl StreamDeck {
+    /// Export the current layout, as set through this object
+    pub fn export_layout(&self) -> Layout {
+        Layout {
+            brightness: self.brightness,
+            keys: self.contents.clone(),
+        }
+    }
+
+    /// Apply a layout, setting the brightness and re-uploading all keys with known content
+    ///
+    /// Keys without content in the layout are left unchanged, keys beyond the device
+    /// key count result in an [Error::InvalidKeyIndex]. Key uploads are written as a
+    /// single frame (see [StreamDeck::frame]).
+    pub fn apply_layout(&mut self, layout: &Layout) -> Result<(), Error> {
+        if layout.keys.len() > self.kind.keys() as usize {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        if let Some(b) = layout.brightness {
+            self.set_brightness(b)?;
+        }
+
+        self.frame(|deck| {
+            for (key, content) in layout.keys.iter().enumerate() {
+                if let Some(content) = content {
+                    deck.set_key_content(key as u8, content)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Apply a layout, only writing the brightness and keys that differ from the current layout
+    ///
+    /// This compares against [StreamDeck::export_layout], so keys with unknown content are
+    /// always uploaded, and image files are compared by path and options rather than contents.
+    /// Otherwise this behaves as [StreamDeck::apply_layout].
+    pub fn apply_layout_diff(&mut self, layout: &Layout) -> Result<(), Error> {
+        if layout.keys.len() > self.kind.keys() as usize {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        if let Some(b) = layout.brightness {
+            if self.brightness != Some(b) {
+                self.set_brightness(b)?;
+            }
+        }
+
+        self.frame(|deck| {
+            for (key, content) in layout.keys.iter().enumerate() {
+                if let Some(content) = content {
+                    if deck.contents[key].as_ref() != Some(content) {
+                        deck.set_key_content(key as u8, content)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Set a key to the provided content
+    pub fn set_key_content(&mut self, key: u8, content: &KeyContent) -> Result<(), Error> {
+        match content {
+            KeyContent::Colour(c) => self.set_button_rgb(key, c),
+            KeyContent::Image { path, options } => self.set_button_file(key, path, options),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Kind, MockTransport};
+
+    #[test]
+    fn export_apply_layout() {
+        let kind = Kind::Mini;
+        let mut deck = StreamDeck::with_transport(MockTransport::new(), kind);
+
+        deck.set_brightness(40).unwrap();
+        deck.set_button_rgb(0, &Colour::RED).unwrap();
+        deck.set_button_file(2, "./icons/power.png", &ImageOptions::default())
+            .unwrap();
+        deck.set_button_rgb(5, &Colour::BLUE).unwrap();
+        // Content from in-memory images is not known
+        deck.set_button_raw(5, &vec![0u8; kind.key_image_bytes_len()])
+            .unwrap();
+
+        let layout = deck.export_layout();
+        assert_eq!(layout.brightness, Some(40));
+        assert_eq!(layout.keys.len(), 6);
+        assert_eq!(layout.keys[0], Some(KeyContent::Colour(Colour::RED)));
+        assert!(
+            matches!(&layout.keys[2], Some(KeyContent::Image { path, .. }) if path == "./icons/power.png")
+        );
+        assert_eq!(layout.keys[5], None);
+
+        // Apply to a fresh device
+        let mock = MockTransport::new();
+        let mut other = StreamDeck::with_transport(mock.clone(), kind);
+        other.apply_layout(&layout).unwrap();
+        assert_eq!(other.export_layout(), layout);
+
+        // Only the keys with content are uploaded
+        let mut keys: Vec<u8> = mock.written_packets().iter().map(|p| p[5]).collect();
+        keys.dedup();
+        assert_eq!(keys, vec![0, 2]);
+        assert_eq!(mock.sent_feature_reports()[0][5], 40);
+
+        // Only differing keys are written when switching layouts
+        let mut next = layout.clone();
+        next.keys[0] = Some(KeyContent::Colour(Colour::GREEN));
+        next.keys[1] = Some(KeyContent::Colour(Colour::WHITE));
+
+        mock.clear();
+        other.apply_layout_diff(&next).unwrap();
+        let mut keys: Vec<u8> = mock.written_packets().iter().map(|p| p[5]).collect();
+        keys.dedup();
+        assert_eq!(keys, vec![0, 1]);
+        assert!(mock.sent_feature_reports().is_empty());
+        assert_eq!(other.export_layout(), next);
+
+        mock.clear();
+        other.apply_layout_diff(&next).unwrap();
+        assert!(mock.written_packets().is_empty());
+
+        // Reset clears the layout
+        other.reset().unwrap();
+        assert_eq!(
+            other.export_layout(),
+            Layout {
+                brightness: None,
+                keys: vec![None; 6]
+            }
+        );
+    }
+}
@@ -1,31 +1,129 @@
 use std::io::Error as IoError;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 #[macro_use]
 extern crate log;
 
-extern crate hidapi;
+#[cfg(feature = "text")]
 use ab_glyph::{FontRef, PxScale};
-use hidapi::{HidApi, HidDevice, HidError};
+#[cfg(feature = "hidapi")]
+use hidapi::{HidApi, HidError};
 
 extern crate image;
-use image::{DynamicImage, ImageBuffer, ImageError, Rgb};
+use image::{imageops::FilterType, DynamicImage, ImageError};
+#[cfg(feature = "text")]
+use image::{ImageBuffer, Rgb};
 
 pub mod images;
 use crate::images::{apply_transform, encode_jpeg};
-pub use crate::images::{Colour, ImageOptions};
+pub use crate::images::{Colour, ImageOptions, ScaleMode};
 
 pub mod info;
+use info::InputType;
 pub use info::*;
 
+pub mod animation;
+pub use animation::{Animator, Easing, FrameSequence};
+
+pub mod button;
+pub use button::{Button, Dial};
+
+pub mod oriented;
+pub use oriented::OrientedImage;
+
+pub mod framebuffer;
+pub use framebuffer::KeyFramebuffer;
+
+pub mod claims;
+pub use claims::KeyClaim;
+use claims::KeyOwners;
+
+pub mod shared;
+pub use shared::SharedStreamDeck;
+
+pub mod deck;
+pub use deck::Deck;
+
+#[cfg(feature = "async")]
+pub mod async_deck;
+#[cfg(feature = "async")]
+pub use async_deck::AsyncStreamDeck;
+
+pub mod reconnect;
+pub use reconnect::{ReconnectPolicy, ReconnectingStreamDeck};
+
+pub mod manager;
+pub use manager::{DeckEvent, DeckManager};
+
+#[cfg(feature = "stream")]
+pub mod stream;
+#[cfg(feature = "stream")]
+pub use stream::InputStream;
+
+pub mod render;
+#[cfg(feature = "text")]
+pub use render::{render_icon_label, IconLabelOptions};
+pub use render::{render_number, render_placeholder, DigitStyle, NumberOptions, PlaceholderStyle};
+
+pub mod layout;
+pub use layout::{KeyContent, Layout};
+
+mod writer;
+use writer::BackgroundWriter;
+
+#[cfg(feature = "hidapi")]
+pub mod watcher;
+#[cfg(feature = "hidapi")]
+pub use watcher::{DeviceEvent, DeviceWatcher};
+
+#[cfg(feature = "hidapi")]
+mod registry;
+#[cfg(feature = "hidapi")]
+use registry::DeviceClaim;
+
+pub mod spec;
+pub use spec::DeviceSpec;
+
+pub mod input;
+pub use input::{
+    ButtonAction, ButtonStates, DialAction, DialInput, DialValue, IndexMode, InputEvent,
+    InputListener, InputManager, InputReport, InputState,
+};
+
+pub mod transport;
+pub use transport::{DeckTransport, MockTransport};
+
+#[cfg(feature = "text")]
 use imageproc::drawing::draw_text_mut;
-use std::str::FromStr;
 use thiserror::Error;
 
 /// StreamDeck object
 pub struct StreamDeck {
     kind: Kind,
-    device: HidDevice,
+    device: Arc<Mutex<Box<dyn DeckTransport>>>,
+    writer: Option<BackgroundWriter>,
+    brightness: Option<u8>,
+    frame: Option<Vec<(u8, DeviceImage)>>,
+    image_options: ImageOptions,
+    upload_chunk_size: usize,
+    contents: Vec<Option<KeyContent>>,
+    orientation: Rotation,
+    strict_input: bool,
+    sleeping: bool,
+    key_data_offset: usize,
+    missing_placeholder: Option<PlaceholderStyle>,
+    blocking: bool,
+    heartbeat: Option<Duration>,
+    last_heartbeat: Instant,
+    connected: bool,
+    key_owners: KeyOwners,
+    claim_token: Option<u64>,
+    oriented_images: Vec<Option<OrientedImage>>,
+    /// Last image written to each key, retained for restoring after reconnecting
+    retained_images: Option<Vec<Option<DeviceImage>>>,
+    #[cfg(feature = "hidapi")]
+    claim: Option<DeviceClaim>,
 }
 
 /// Helper object for filtering device connections
@@ -41,16 +139,44 @@ pub struct Filter {
     pub pid: u16,
 
     #[structopt(long, env = "USB_SERIAL")]
-    /// USB Device Serial
+    /// USB Device Serial, or a unique prefix of the serial
     pub serial: Option<String>,
+
+    #[structopt(long)]
+    /// Require an exact serial match
+    pub exact_serial: bool,
+}
+
+#[cfg(feature = "structopt")]
+impl Filter {
+    /// Fetch the serial matching mode for this filter
+    pub fn serial_match(&self) -> SerialMatch {
+        if self.exact_serial {
+            SerialMatch::Exact
+        } else {
+            SerialMatch::Prefix
+        }
+    }
+}
+
+/// Serial matching mode used when connecting
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SerialMatch {
+    /// Serial must match exactly
+    Exact,
+    /// Serial may be a prefix of a single attached device serial,
+    /// an exact match is always preferred
+    Prefix,
 }
 
+#[cfg(feature = "structopt")]
 fn u16_parse_hex(s: &str) -> Result<u16, std::num::ParseIntError> {
     u16::from_str_radix(s, 16)
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[cfg(feature = "hidapi")]
     #[error(transparent)]
     Hid(#[from] HidError),
     #[error(transparent)]
@@ -58,16 +184,64 @@ pub enum Error {
     #[error(transparent)]
     Image(#[from] ImageError),
 
-    #[error("invalid image size")]
-    InvalidImageSize,
+    #[error("invalid image size ({got} bytes, expected {expected})")]
+    InvalidImageSize { got: usize, expected: usize },
+    #[error("unsupported BMP image width {width}, must be a multiple of 4 pixels")]
+    UnalignedBmpWidth { width: usize },
     #[error("invalid key index")]
     InvalidKeyIndex,
+    #[error("invalid dial index")]
+    InvalidDialIndex,
     #[error("unrecognised pid")]
     UnrecognisedPID,
     #[error("no data")]
     NoData,
+    #[error("invalid report")]
+    InvalidReport,
+    #[error("invalid upload chunk size")]
+    InvalidChunkSize,
+    #[error("invalid sprite sheet cell index")]
+    InvalidCellIndex,
+    #[error("invalid sprite sheet grid ({cols}x{rows} cells for a {width}x{height} image)")]
+    InvalidSheetGrid {
+        cols: u32,
+        rows: u32,
+        width: u32,
+        height: u32,
+    },
+    #[error("device busy, it may be open in another process")]
+    Busy,
+    #[error("failed to open device: {0}")]
+    OpenFailed(String),
+    #[error("permission denied opening device ({0}), on linux install the udev rules from 40-streamdeck.rules and reconnect the device")]
+    PermissionDenied(String),
+    #[error("timeout")]
+    Timeout,
+    #[error("unsupported input report type 0x{report_type:02x}")]
+    UnsupportedInput { report_type: u8 },
+    #[error("device already open in this process")]
+    AlreadyOpen,
+    #[error("device identifier already in use: {0}")]
+    DuplicateId(String),
+    #[error("device index out of range")]
+    IndexOutOfRange,
+    #[error("unsupported orientation")]
+    UnsupportedOrientation,
+    #[error("serial matches multiple devices: {0:?}")]
+    AmbiguousSerial(Vec<String>),
+    #[error("device disconnected")]
+    Disconnected,
+    #[error("device is in bootloader mode, power-cycle the device to return to normal operation")]
+    InBootloaderMode,
+    #[error("key {key} claimed by {owner}")]
+    KeyClaimed { key: u8, owner: String },
+    #[error("device has no display")]
+    NoDisplay,
+    #[error("device has no info bar")]
+    NoInfoBar,
 }
 
+#[derive(Clone)]
 pub struct DeviceImage {
     data: Vec<u8>,
 }
@@ -85,54 +259,305 @@ impl From<Vec<u8>> for DeviceImage {
     }
 }
 
+/// Elgato USB Vendor Identifier (VID)
+pub const ELGATO_VID: u16 = 0x0fd9;
+
+/// Information on an attached device, see [StreamDeck::list]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    /// USB Vendor ID
+    pub vid: u16,
+    /// USB Product ID
+    pub pid: u16,
+    /// USB serial, if available
+    pub serial: Option<String>,
+    /// Platform specific device path
+    pub path: String,
+    /// Device kind, resolved from the PID
+    pub kind: Kind,
+}
+
 /// Device USB Product Identifiers (PIDs)
 pub mod pids {
     pub const ORIGINAL: u16 = 0x0060;
     pub const ORIGINAL_V2: u16 = 0x006d;
     pub const MINI: u16 = 0x0063;
     pub const XL: u16 = 0x006c;
+    pub const XL_V2: u16 = 0x008f;
     pub const MK2: u16 = 0x0080;
+    pub const MK2_SCISSOR: u16 = 0x00a5;
     pub const REVISED_MINI: u16 = 0x0090;
+    pub const MINI_DISCORD: u16 = 0x00b3;
+    pub const PEDAL: u16 = 0x0086;
+    pub const NEO: u16 = 0x009a;
+    pub const STUDIO: u16 = 0x00aa;
+    pub const MODULE_6: u16 = 0x00b8;
+    pub const MODULE_15: u16 = 0x00b9;
+    pub const MODULE_32: u16 = 0x00ba;
 }
 
 impl StreamDeck {
+    /// Create a streamdeck object using the provided transport
+    ///
+    /// This allows devices to be driven over transports other than hidapi,
+    /// the device kind must be provided as it cannot be detected from the transport.
+    pub fn with_transport<T: DeckTransport + 'static>(transport: T, kind: Kind) -> StreamDeck {
+        StreamDeck {
+            device: Arc::new(Mutex::new(Box::new(transport))),
+            writer: None,
+            kind,
+            brightness: None,
+            frame: None,
+            image_options: ImageOptions::default(),
+            upload_chunk_size: kind.max_upload_chunk_size(),
+            contents: vec![None; kind.keys() as usize],
+            orientation: Rotation::Rot0,
+            strict_input: false,
+            sleeping: false,
+            key_data_offset: kind.key_data_offset(),
+            missing_placeholder: None,
+            blocking: true,
+            heartbeat: None,
+            last_heartbeat: Instant::now(),
+            connected: true,
+            key_owners: KeyOwners::new(kind.keys()),
+            claim_token: None,
+            oriented_images: vec![None; kind.keys() as usize],
+            retained_images: None,
+            #[cfg(feature = "hidapi")]
+            claim: None,
+        }
+    }
+
     /// Connect to a streamdeck device
+    ///
+    /// Each device may only be opened once per process, with further attempts failing
+    /// with [Error::AlreadyOpen]. Use [SharedStreamDeck] to share a device.
+    /// The serial may be a unique prefix of the device serial, see [SerialMatch::Prefix].
+    #[cfg(feature = "hidapi")]
     pub fn connect(vid: u16, pid: u16, serial: Option<String>) -> Result<StreamDeck, Error> {
+        StreamDeck::connect_matching(vid, pid, serial, SerialMatch::Prefix)
+    }
+
+    /// Connect to a streamdeck device using the provided serial matching mode
+    #[cfg(feature = "hidapi")]
+    pub fn connect_matching(
+        vid: u16,
+        pid: u16,
+        serial: Option<String>,
+        mode: SerialMatch,
+    ) -> Result<StreamDeck, Error> {
         // Create new API
         let api = HidApi::new()?;
-        StreamDeck::connect_with_hid(&api, vid, pid, serial)
+        StreamDeck::connect_with_hid_matching(&api, vid, pid, serial, mode)
+    }
+
+    /// Connect to a streamdeck device, failing with [Error::Timeout] if this does not
+    /// complete within the provided timeout
+    ///
+    /// Opening a device can block on some platforms when it is held by another process.
+    /// The connection is attempted on a separate thread, which is left to complete
+    /// (and close any device it opens) in the background if the timeout elapses.
+    #[cfg(feature = "hidapi")]
+    pub fn connect_timeout(
+        vid: u16,
+        pid: u16,
+        serial: Option<String>,
+        timeout: Duration,
+    ) -> Result<StreamDeck, Error> {
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            let _ = tx.send(StreamDeck::connect(vid, pid, serial));
+        });
+
+        match rx.recv_timeout(timeout) {
+            Ok(r) => r,
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+
+    /// List attached devices
+    ///
+    /// This uses HID enumeration only and does not open any devices,
+    /// so is suitable for repeated polling. Unrecognised PIDs are skipped.
+    #[cfg(feature = "hidapi")]
+    pub fn list() -> Result<Vec<DeviceInfo>, Error> {
+        let api = HidApi::new()?;
+        Ok(StreamDeck::list_with_hid(&api))
+    }
+
+    /// List attached devices with an already initialised HidApi instance
+    ///
+    /// See [StreamDeck::list], call [HidApi::refresh_devices] to update the device list.
+    #[cfg(feature = "hidapi")]
+    pub fn list_with_hid(api: &HidApi) -> Vec<DeviceInfo> {
+        let mut devices: Vec<DeviceInfo> = Vec::new();
+
+        for d in api.device_list() {
+            if d.vendor_id() != ELGATO_VID {
+                continue;
+            }
+            let kind = match Kind::from_pid(d.product_id()) {
+                Some(k) => k,
+                None => {
+                    if is_bootloader(d.product_string()) {
+                        warn!(
+                            "Device {:04x}:{:04x} is in bootloader mode, power-cycle to use",
+                            d.vendor_id(),
+                            d.product_id()
+                        );
+                    }
+                    continue;
+                }
+            };
+            let path = d.path().to_string_lossy().into_owned();
+
+            // Composite devices may enumerate an interface more than once
+            if devices.iter().any(|i| i.path == path) {
+                continue;
+            }
+
+            devices.push(DeviceInfo {
+                vid: d.vendor_id(),
+                pid: d.product_id(),
+                serial: d.serial_number().map(|s| s.to_string()),
+                path,
+                kind,
+            });
+        }
+
+        devices
+    }
+
+    /// List attached devices of the provided kind
+    ///
+    /// Devices are matched by [Kind::from_pid], so this includes all hardware revisions
+    /// of the kind. See [StreamDeck::list].
+    #[cfg(feature = "hidapi")]
+    pub fn list_kind(kind: Kind) -> Result<Vec<DeviceInfo>, Error> {
+        Ok(filter_kind(StreamDeck::list()?, kind))
+    }
+
+    /// List attached devices of the provided kind with an already initialised HidApi instance
+    ///
+    /// See [StreamDeck::list_kind].
+    #[cfg(feature = "hidapi")]
+    pub fn list_kind_with_hid(api: &HidApi, kind: Kind) -> Vec<DeviceInfo> {
+        filter_kind(StreamDeck::list_with_hid(api), kind)
     }
 
     /// Connect to a streamdeck device with an already initialise HidApi instance
+    ///
+    /// The serial may be a unique prefix of the device serial, see [SerialMatch::Prefix].
+    #[cfg(feature = "hidapi")]
     pub fn connect_with_hid(
         api: &HidApi,
         vid: u16,
         pid: u16,
         serial: Option<String>,
+    ) -> Result<StreamDeck, Error> {
+        StreamDeck::connect_with_hid_matching(api, vid, pid, serial, SerialMatch::Prefix)
+    }
+
+    /// Connect to a streamdeck device with an already initialise HidApi instance,
+    /// using the provided serial matching mode
+    #[cfg(feature = "hidapi")]
+    pub fn connect_with_hid_matching(
+        api: &HidApi,
+        vid: u16,
+        pid: u16,
+        serial: Option<String>,
+        mode: SerialMatch,
     ) -> Result<StreamDeck, Error> {
         // Match info based on PID
-        let kind = match pid {
-            pids::ORIGINAL => Kind::Original,
-            pids::MINI => Kind::Mini,
+        let kind = Kind::from_pid(pid).ok_or(Error::UnrecognisedPID)?;
 
-            pids::ORIGINAL_V2 => Kind::OriginalV2,
-            pids::XL => Kind::Xl,
-            pids::MK2 => Kind::Mk2,
-            pids::REVISED_MINI => Kind::RevisedMini,
+        debug!("Device info: {:?}", kind);
 
-            _ => return Err(Error::UnrecognisedPID),
+        // Resolve partial serials against attached devices, where nothing matches
+        // the serial is used as provided so the open error is reported
+        let serial = match (serial, mode) {
+            (Some(s), SerialMatch::Prefix) => {
+                let serials = api
+                    .device_list()
+                    .filter(|d| d.vendor_id() == vid && d.product_id() == pid)
+                    .filter_map(|d| d.serial_number());
+                Some(match_serial(serials, &s)?.unwrap_or(s))
+            }
+            (s, _) => s,
         };
 
-        debug!("Device info: {:?}", kind);
+        // Resolve the device path, so devices already open in this process are
+        // rejected before attempting to open them again
+        let path = api
+            .device_list()
+            .find(|d| {
+                d.vendor_id() == vid
+                    && d.product_id() == pid
+                    && (serial.is_none() || d.serial_number() == serial.as_deref())
+            })
+            .map(|d| d.path());
+
+        // Devices left in bootloader mode enumerate with another pid, so check for
+        // these where the device is not found to report a useful error
+        if path.is_none()
+            && api.device_list().any(|d| {
+                d.vendor_id() == vid
+                    && is_bootloader(d.product_string())
+                    && (serial.is_none() || d.serial_number() == serial.as_deref())
+            })
+        {
+            return Err(Error::InBootloaderMode);
+        }
+
+        let claim = path
+            .map(|p| DeviceClaim::new(&p.to_string_lossy()))
+            .transpose()?;
 
         // Attempt to connect to device
-        let device = match &serial {
-            Some(s) => api.open_serial(vid, pid, s),
-            None => api.open(vid, pid),
-        }?;
+        let device = match (path, &serial) {
+            (Some(p), _) => api.open_path(p),
+            (None, Some(s)) => api.open_serial(vid, pid, s),
+            (None, None) => api.open(vid, pid),
+        }
+        .map_err(open_error)?;
 
         // Return streamdeck object
-        Ok(StreamDeck { device, kind })
+        let mut deck = StreamDeck::with_transport(device, kind);
+        deck.claim = claim;
+
+        Ok(deck)
+    }
+
+    /// Connect to the n-th (zero indexed) device matching the vid and pid,
+    /// failing with [Error::IndexOutOfRange] if there are not enough matching devices
+    ///
+    /// Enumeration order is platform dependent and may change when devices are
+    /// reconnected or the host restarts, so prefer serials for identifying devices
+    /// persistently.
+    #[cfg(feature = "hidapi")]
+    pub fn connect_nth(vid: u16, pid: u16, n: usize) -> Result<StreamDeck, Error> {
+        let api = HidApi::new()?;
+
+        let kind = Kind::from_pid(pid).ok_or(Error::UnrecognisedPID)?;
+
+        // Devices may enumerate more than one interface
+        let mut paths = Vec::new();
+        for d in api.device_list() {
+            if d.vendor_id() == vid && d.product_id() == pid && !paths.contains(&d.path()) {
+                paths.push(d.path());
+            }
+        }
+
+        let path = paths.get(n).ok_or(Error::IndexOutOfRange)?;
+        let claim = DeviceClaim::new(&path.to_string_lossy())?;
+        let device = api.open_path(path).map_err(open_error)?;
+
+        let mut deck = StreamDeck::with_transport(device, kind);
+        deck.claim = Some(claim);
+
+        Ok(deck)
     }
 
     /// Fetch the connected device kind
@@ -143,22 +568,46 @@ impl StreamDeck {
         self.kind
     }
 
+    /// Fetch the number of keys on the connected device
+    pub fn keys(&self) -> u8 {
+        self.kind.keys()
+    }
+
+    /// Fetch the number of dials on the connected device, zero for devices without dials
+    pub fn dials(&self) -> u8 {
+        self.kind.dials()
+    }
+
+    /// Return [Error::NoDisplay] for devices without a display, see [Kind::has_display]
+    fn check_display(&self) -> Result<(), Error> {
+        match self.kind.has_display() {
+            true => Ok(()),
+            false => Err(Error::NoDisplay),
+        }
+    }
+
+    /// Lock the underlying transport, shared with the background writer if enabled
+    fn device(&self) -> MutexGuard<'_, Box<dyn DeckTransport>> {
+        // A panic while holding the lock leaves the transport itself usable
+        self.device.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
     /// Fetch the device manufacturer string
     pub fn manufacturer(&mut self) -> Result<String, Error> {
-        let s = self.device.get_manufacturer_string()?;
-        Ok(s.unwrap())
+        let s = self.device().manufacturer()?;
+        s.ok_or(Error::NoData)
     }
 
     /// Fetch the device product string
     pub fn product(&mut self) -> Result<String, Error> {
-        let s = self.device.get_product_string()?;
-        Ok(s.unwrap())
+        let s = self.device().product()?;
+        s.ok_or(Error::NoData)
     }
 
     /// Fetch the device serial
     pub fn serial(&mut self) -> Result<String, Error> {
-        let s = self.device.get_serial_number_string()?;
-        Ok(s.unwrap())
+        let s = self.device().serial()?;
+        s.ok_or(Error::NoData)
     }
 
     /// Fetch the device firmware version
@@ -166,10 +615,21 @@ impl StreamDeck {
         let mut buff = [0u8; 17];
         buff[0] = if self.kind.is_v2() { 0x05 } else { 0x04 };
 
-        let _s = self.device.get_feature_report(&mut buff)?;
+        let n = self.device().get_feature_report(&mut buff)?;
 
         let offset = if self.kind.is_v2() { 6 } else { 5 };
-        Ok(std::str::from_utf8(&buff[offset..]).unwrap().to_string())
+        let data = buff
+            .get(offset..n.min(buff.len()))
+            .ok_or(Error::InvalidReport)?;
+
+        let version = std::str::from_utf8(data).map_err(|_| Error::InvalidReport)?;
+        Ok(version.trim_end_matches('\0').to_string())
+    }
+
+    /// Fetch and parse the device firmware version
+    pub fn firmware_version(&mut self) -> Result<FirmwareVersion, Error> {
+        let version = self.version()?;
+        Ok(FirmwareVersion::parse(&version))
     }
 
     /// Reset the connected device
@@ -182,24 +642,134 @@ impl StreamDeck {
             cmd[..2].copy_from_slice(&[0x0b, 0x63]);
         }
 
-        self.device.send_feature_report(&cmd)?;
+        // Pending background uploads would be drawn over the reset device
+        if let Some(w) = &self.writer {
+            w.discard();
+        }
+
+        self.device().send_feature_report(&cmd)?;
+
+        // Reset restores the device default brightness and clears the keys,
+        // including any writes buffered while asleep
+        if self.sleeping {
+            self.sleeping = false;
+            self.frame = None;
+        }
+        self.brightness = None;
+        self.contents.iter_mut().for_each(|c| *c = None);
+        self.oriented_images.iter_mut().for_each(|i| *i = None);
+        self.retained_images
+            .iter_mut()
+            .flatten()
+            .for_each(|i| *i = None);
+
+        Ok(())
+    }
+
+    /// Reset the connected device, then restore the brightness previously set with
+    /// [StreamDeck::set_brightness]
+    ///
+    /// Where no brightness has been set this is equivalent to [StreamDeck::reset].
+    pub fn reset_keep_brightness(&mut self) -> Result<(), Error> {
+        let brightness = self.brightness;
+
+        self.reset()?;
+
+        if let Some(b) = brightness {
+            self.set_brightness(b)?;
+        }
 
         Ok(())
     }
 
     /// Set the device display brightness (in percent)
+    ///
+    /// The percentage is mapped to the device specific value using [Kind::brightness_raw].
+    ///
+    /// While asleep (see [StreamDeck::set_sleep]) the brightness is applied on wake.
     pub fn set_brightness(&mut self, brightness: u8) -> Result<(), Error> {
-        let mut cmd = [0u8; 17];
-
+        self.check_display()?;
         let brightness = brightness.min(100);
 
+        if !self.sleeping {
+            self.write_brightness(brightness)?;
+        }
+        self.brightness = Some(brightness);
+
+        Ok(())
+    }
+
+    /// Write the display brightness (in percent) to the device
+    fn write_brightness(&mut self, brightness: u8) -> Result<(), Error> {
+        self.check_display()?;
+        let mut cmd = [0u8; 17];
+        let raw = self.kind.brightness_raw(brightness);
+
         if self.kind.is_v2() {
-            cmd[..3].copy_from_slice(&[0x03, 0x08, brightness]);
+            cmd[..3].copy_from_slice(&[0x03, 0x08, raw]);
         } else {
-            cmd[..6].copy_from_slice(&[0x05, 0x55, 0xaa, 0xd1, 0x01, brightness]);
+            cmd[..6].copy_from_slice(&[0x05, 0x55, 0xaa, 0xd1, 0x01, raw]);
+        }
+
+        self.device().send_feature_report(&cmd)?;
+
+        Ok(())
+    }
+
+    /// Put the display to sleep, or wake it
+    ///
+    /// Sleeping turns the display off, and button image writes are buffered (as for
+    /// [StreamDeck::begin_frame]) rather than sent to the device, including across calls
+    /// to [StreamDeck::flush]. On wake the last brightness set is restored (or full
+    /// brightness where unknown) and buffered images are flushed, so the display shows
+    /// the latest state. Input is still read while asleep.
+    pub fn set_sleep(&mut self, sleep: bool) -> Result<(), Error> {
+        if sleep == self.sleeping {
+            return Ok(());
+        }
+
+        if sleep {
+            self.write_brightness(0)?;
+            self.begin_frame();
+            self.sleeping = true;
+            return Ok(());
+        }
+
+        self.sleeping = false;
+        self.write_brightness(self.brightness.unwrap_or(100))?;
+        self.flush()
+    }
+
+    /// Check whether the display is asleep, see [StreamDeck::set_sleep]
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    /// Fade the device display brightness (in percent) to the target over the provided duration
+    ///
+    /// This blocks for the duration of the fade, stepping in 1% increments from the
+    /// last brightness set on this object. Where the current brightness is unknown
+    /// (ie. nothing has been set since connecting or resetting) the target is applied immediately.
+    pub fn fade_brightness(&mut self, target: u8, duration: Duration) -> Result<(), Error> {
+        let target = target.min(100);
+
+        let current = match self.brightness {
+            Some(b) => b,
+            None => return self.set_brightness(target),
+        };
+
+        let steps = current.abs_diff(target) as u32;
+        if steps == 0 {
+            return Ok(());
         }
 
-        self.device.send_feature_report(&cmd)?;
+        let interval = duration / steps;
+        for i in 1..=steps {
+            std::thread::sleep(interval);
+
+            let delta = (target as i32 - current as i32) * i as i32 / steps as i32;
+            self.set_brightness((current as i32 + delta) as u8)?;
+        }
 
         Ok(())
     }
@@ -208,7 +778,42 @@ impl StreamDeck {
     ///
     /// See: `read_buttons` for discussion of this functionality
     pub fn set_blocking(&mut self, blocking: bool) -> Result<(), Error> {
-        self.device.set_blocking_mode(blocking)?;
+        self.device().set_blocking_mode(blocking)?;
+        self.blocking = blocking;
+
+        Ok(())
+    }
+
+    /// Set the interval for heartbeat checks while reading input, disabled (`None`) by default
+    ///
+    /// Some platforms do not report an error when a device is unplugged, leaving blocking
+    /// reads waiting indefinitely. With a heartbeat set, blocking reads wake at least once per
+    /// interval and, where no input has been received within the interval, request a cheap
+    /// feature report (the firmware version) from the device. If this fails the device is
+    /// marked as disconnected and reads return [Error::Disconnected].
+    pub fn set_heartbeat_interval(&mut self, interval: Option<Duration>) {
+        self.heartbeat = interval;
+        self.last_heartbeat = Instant::now();
+    }
+
+    /// Check whether the device is connected, this is only updated by heartbeat checks,
+    /// see [StreamDeck::set_heartbeat_interval]
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Run a heartbeat check where the interval has elapsed
+    fn heartbeat_if_due(&mut self, interval: Duration) -> Result<(), Error> {
+        if self.last_heartbeat.elapsed() < interval {
+            return Ok(());
+        }
+        self.last_heartbeat = Instant::now();
+
+        if let Err(e) = self.version() {
+            warn!("Heartbeat failed, marking device as disconnected: {}", e);
+            self.connected = false;
+            return Err(Error::Disconnected);
+        }
 
         Ok(())
     }
@@ -217,38 +822,241 @@ impl StreamDeck {
     ///
     /// In blocking mode this will wait until a report packet has been received
     /// (or the specified timeout has elapsed). In non-blocking mode this will return
-    /// immediately with a zero vector if no data is available
-    pub fn read_buttons(&mut self, timeout: Option<Duration>) -> Result<Vec<u8>, Error> {
-        let mut cmd = [0u8; 36];
-        let keys = self.kind.keys() as usize;
-        let offset = self.kind.key_data_offset();
-
-        match timeout {
-            Some(t) => self
-                .device
-                .read_timeout(&mut cmd[..keys + offset + 1], t.as_millis() as i32)?,
-            None => self.device.read(&mut cmd[..keys + offset + 1])?,
+    /// immediately with [Error::NoData] if no data is available
+    pub fn read_buttons(&mut self, timeout: Option<Duration>) -> Result<ButtonStates, Error> {
+        self.read_buttons_raw(timeout).map(ButtonStates::new)
+    }
+
+    /// Fetch raw button states, with one byte per key (non-zero when pressed)
+    ///
+    /// See [StreamDeck::read_buttons] for the timeout and blocking behaviour.
+    pub fn read_buttons_raw(&mut self, timeout: Option<Duration>) -> Result<Vec<u8>, Error> {
+        self.read_buttons_report(timeout)
+            .map(|(buttons, _)| buttons)
+    }
+
+    /// Fetch raw button states along with the input report they were decoded from,
+    /// including the leading report ID
+    ///
+    /// Dial reports are not button states, so are skipped, reading on until a button report
+    /// is received (or the timeout has elapsed).
+    pub(crate) fn read_buttons_report(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        loop {
+            let timeout = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+
+            match self.read_input_report_raw(timeout)? {
+                (InputReport::Buttons(buttons), raw) => return Ok((buttons, raw)),
+                (InputReport::Dials(_), _) => debug!("Skipping dial report when reading buttons"),
+            }
+        }
+    }
+
+    /// Fetch and decode a single input report, button states or dial input
+    ///
+    /// Unlike [StreamDeck::read_buttons], dial reports are returned rather than skipped.
+    /// See [StreamDeck::read_buttons] for the timeout and blocking behaviour.
+    pub fn read_input_report(&mut self, timeout: Option<Duration>) -> Result<InputReport, Error> {
+        self.read_input_report_raw(timeout)
+            .map(|(report, _)| report)
+    }
+
+    /// Fetch and decode a single input report, along with the raw report it was decoded
+    /// from (including the leading report ID)
+    pub(crate) fn read_input_report_raw(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<(InputReport, Vec<u8>), Error> {
+        let (input_type, report) = self.read_input(timeout)?;
+
+        let decoded = match input_type {
+            InputType::Keys => {
+                let mut buttons = decode_buttons(self.kind, self.key_data_offset, &report)?;
+
+                // Touch points follow the keys and are not remapped
+                if self.orientation == Rotation::Rot180 {
+                    buttons[..self.kind.keys() as usize].reverse();
+                }
+                InputReport::Buttons(buttons)
+            }
+            InputType::Dials => InputReport::Dials(decode_dials(self.kind, &report)?),
         };
 
-        if cmd[0] == 0 {
-            return Err(Error::NoData);
+        let mut raw = Vec::with_capacity(report.len() + 1);
+        raw.push(self.kind.input_report_id());
+        raw.extend_from_slice(&report);
+
+        Ok((decoded, raw))
+    }
+
+    /// Set the mounting orientation of the device
+    ///
+    /// For devices mounted upside down ([Rotation::Rot180]), images are rotated and
+    /// key indices remapped for both writes and reads, so key 0 remains top left as
+    /// seen by the user. Only [Rotation::Rot0] and [Rotation::Rot180] are supported
+    /// as no devices have square key layouts, others return [Error::UnsupportedOrientation].
+    ///
+    /// Images already shown are not redrawn, other than those set with
+    /// [StreamDeck::set_button_oriented], and images encoded before changing the
+    /// orientation (eg. with [StreamDeck::load_image]) are not rotated.
+    pub fn set_orientation(&mut self, orientation: Rotation) -> Result<(), Error> {
+        if !matches!(orientation, Rotation::Rot0 | Rotation::Rot180) {
+            return Err(Error::UnsupportedOrientation);
+        }
+
+        if orientation == self.orientation {
+            return Ok(());
+        }
+
+        self.contents.iter_mut().for_each(|c| *c = None);
+        self.retained_images
+            .iter_mut()
+            .flatten()
+            .for_each(|i| *i = None);
+        self.orientation = orientation;
+
+        // Images registered per orientation are redrawn
+        self.redraw_oriented()
+    }
+
+    /// Fetch the mounting orientation of the device
+    pub fn orientation(&self) -> Rotation {
+        self.orientation.clone()
+    }
+
+    /// Map a key index to the device key index for the current orientation
+    fn oriented_key_index(&self, key: u8) -> Result<u8, Error> {
+        let keys = self.kind.keys();
+        if key >= keys {
+            return Err(Error::InvalidKeyIndex);
         }
 
-        let mut out = vec![0u8; keys];
+        let key = match self.orientation {
+            Rotation::Rot180 => keys - 1 - key,
+            _ => key,
+        };
+        self.kind.translate_key_index(key)
+    }
+
+    /// Map a key index to the position of the key in input reports
+    ///
+    /// Key states are read in the order keys appear in input reports, and remapped to
+    /// the same (logical) indices as images by the device key direction and orientation.
+    /// This reverses that mapping, for correlating keys with raw reports. Touch points
+    /// (see [Kind::touch_points]) follow the keys and are not remapped.
+    pub fn physical_key_index(&self, key: u8) -> Result<u8, Error> {
+        let keys = self.kind.keys();
+        if key >= keys {
+            return match key < self.kind.buttons() {
+                true => Ok(key),
+                false => Err(Error::InvalidKeyIndex),
+            };
+        }
+
+        let key = match self.orientation {
+            Rotation::Rot180 => keys - 1 - key,
+            _ => key,
+        };
+
         match self.kind.key_direction() {
-            KeyDirection::RightToLeft => {
-                for (i, val) in out.iter_mut().enumerate() {
-                    // In right-to-left mode(original Streamdeck) the first key has index 1,
-                    // so we don't add the +1 here.
-                    *val = cmd[offset + self.translate_key_index(i as u8)? as usize];
+            // Right-to-left keys are 1-indexed
+            KeyDirection::RightToLeft => Ok(self.kind.translate_key_index(key)? - 1),
+            KeyDirection::LeftToRight => Ok(key),
+        }
+    }
+
+    /// Read a single input report from the device
+    ///
+    /// The buffer is sized for the report ID, header (see [StreamDeck::key_data_offset])
+    /// and button states, and the leading report ID is checked and stripped, so the returned
+    /// data starts with the report header followed by the key states (or other input,
+    /// according to the returned input type).
+    fn read_input(&mut self, timeout: Option<Duration>) -> Result<(InputType, Vec<u8>), Error> {
+        if !self.connected {
+            return Err(Error::Disconnected);
+        }
+
+        let interval = match self.heartbeat {
+            Some(i) => i,
+            None => return self.read_report(timeout),
+        };
+
+        // Non-blocking reads without a timeout return immediately, so need not be split
+        if !self.blocking && timeout.is_none() {
+            self.heartbeat_if_due(interval)?;
+            return self.read_report(None);
+        }
+
+        // Otherwise read in slices of at most the interval, checking the heartbeat between
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            self.heartbeat_if_due(interval)?;
+
+            let wait = match deadline {
+                Some(d) => d.saturating_duration_since(Instant::now()).min(interval),
+                None => interval,
+            };
+
+            match self.read_report(Some(wait)) {
+                Err(Error::NoData) if deadline.is_none_or(|d| Instant::now() < d) => continue,
+                Ok(report) => {
+                    // Input shows the device is still present
+                    self.last_heartbeat = Instant::now();
+                    return Ok(report);
                 }
+                r => return r,
             }
-            KeyDirection::LeftToRight => {
-                out[0..keys].copy_from_slice(&cmd[1 + offset..1 + offset + keys]);
-            }
+        }
+    }
+
+    /// Read and validate a single input report
+    fn read_report(&mut self, timeout: Option<Duration>) -> Result<(InputType, Vec<u8>), Error> {
+        let mut cmd = vec![0u8; 1 + self.key_data_offset + self.kind.buttons() as usize];
+
+        let n = self.device().read(&mut cmd, timeout)?;
+
+        if n == 0 || cmd[0] == 0 {
+            return Err(Error::NoData);
+        }
+
+        if cmd[0] != self.kind.input_report_id() {
+            debug!("Ignoring unexpected report id: 0x{:02x}", cmd[0]);
+            return Err(Error::NoData);
         }
 
-        Ok(out)
+        cmd.truncate(n);
+        cmd.remove(0);
+
+        trace!("Input report: {:02x?}", cmd);
+
+        let input_type = dispatch_input(self.kind, self.strict_input, &cmd)?;
+        Ok((input_type, cmd))
+    }
+
+    /// Override the offset of key states in input reports (after the report ID)
+    ///
+    /// This defaults to [Kind::key_data_offset], and allows input to be decoded for
+    /// devices where firmware changes have moved the key states within the report.
+    pub fn set_key_data_offset(&mut self, offset: usize) {
+        self.key_data_offset = offset;
+    }
+
+    /// Fetch the offset of key states in input reports
+    pub fn key_data_offset(&self) -> usize {
+        self.key_data_offset
+    }
+
+    /// Enable or disable strict input report checking
+    ///
+    /// When enabled, reads from V2 devices return [Error::UnsupportedInput] for input
+    /// types other than key state, rather than decoding them as key states.
+    /// Disabled by default.
+    pub fn set_strict_input(&mut self, strict: bool) {
+        self.strict_input = strict;
     }
 
     /// Fetch image size for the connected device
@@ -258,60 +1066,196 @@ impl StreamDeck {
 
     /// Convert an image into the device dependent format
     fn convert_image(&self, image: Vec<u8>) -> Result<DeviceImage, Error> {
+        self.check_display()?;
+
         // Check image dimensions
-        if image.len() != self.kind.image_size_bytes() {
-            return Err(Error::InvalidImageSize);
+        let expected = self.kind.key_image_bytes_len();
+        if image.len() != expected {
+            return Err(Error::InvalidImageSize {
+                got: image.len(),
+                expected,
+            });
         }
+        let (w, h) = self.kind.image_size();
         let image = match self.kind.image_mode() {
-            ImageMode::Bmp => image,
-            ImageMode::Jpeg => {
-                let (w, h) = self.kind.image_size();
-                encode_jpeg(&image, w, h)?
+            // BMP rows are sent without padding, so would be skewed for other widths
+            ImageMode::Bmp if !w.is_multiple_of(4) => {
+                return Err(Error::UnalignedBmpWidth { width: w });
             }
+            ImageMode::Bmp => image,
+            ImageMode::Jpeg => encode_jpeg(&image, w, h)?,
         };
         Ok(DeviceImage { data: image })
     }
 
     /// Set a button to the provided RGB colour
-    pub fn set_button_rgb(&mut self, key: u8, colour: &Colour) -> Result<(), Error> {
-        let mut image = vec![0u8; self.kind.image_size_bytes()];
+    ///
+    /// This accepts a [Colour] (or reference to one) or an [image::Rgb] pixel.
+    pub fn set_button_rgb(&mut self, key: u8, colour: impl Into<Colour>) -> Result<(), Error> {
+        let colour = colour.into();
+        let mut image = vec![0u8; self.kind.key_image_bytes_len()];
         let colour_order = self.kind.image_colour_order();
 
-        for i in 0..image.len() {
-            match i % 3 {
-                0 => {
-                    image[i] = match colour_order {
-                        ColourOrder::BGR => colour.b,
-                        ColourOrder::RGB => colour.r,
-                    }
-                }
-                1 => image[i] = colour.g,
-                2 => {
-                    image[i] = match colour_order {
-                        ColourOrder::BGR => colour.r,
-                        ColourOrder::RGB => colour.b,
-                    }
-                }
-                _ => unreachable!(),
+        for pixel in image.chunks_exact_mut(3) {
+            let (first, last) = match colour_order {
+                ColourOrder::Bgr => (colour.b, colour.r),
+                ColourOrder::Rgb => (colour.r, colour.b),
             };
+            pixel.copy_from_slice(&[first, colour.g, last]);
         }
         self.write_button_image(key, &self.convert_image(image)?)?;
+        self.set_content(key, Some(KeyContent::Colour(colour)));
 
         Ok(())
     }
 
+    /// Set a button to the provided raw pixel data
+    ///
+    /// Data must be [Kind::key_image_bytes_len] bytes of 8-bit pixels, with the device
+    /// colour order and rotation / mirroring already applied.
+    pub fn set_button_raw(&mut self, key: u8, data: &[u8]) -> Result<(), Error> {
+        let image = self.convert_image(data.to_vec())?;
+        self.write_button_image(key, &image)
+    }
+
     /// Set a button to the provided image
+    ///
+    /// The image must match the device image size, see [Kind::image_size].
     pub fn set_button_image(&mut self, key: u8, image: DynamicImage) -> Result<(), Error> {
-        let image = apply_transform(image, self.kind.image_rotation(), self.kind.image_mirror());
+        let image = self.encode_image(image)?;
+        self.write_button_image(key, &image)
+    }
+
+    /// Encode an image of the device image size into the device specific representation
+    pub(crate) fn encode_image(&self, image: DynamicImage) -> Result<DeviceImage, Error> {
+        let rotation = self.orientation.then(&self.kind.image_rotation());
+        let image = apply_transform(image, rotation, self.kind.image_mirror());
         let mut data = image.into_rgb8().into_vec();
-        if matches!(self.kind.image_colour_order(), ColourOrder::BGR) {
+        if matches!(self.kind.image_colour_order(), ColourOrder::Bgr) {
             rgb_to_bgr(&mut data);
         }
-        self.write_button_image(key, &self.convert_image(data)?)
+        self.convert_image(data)
+    }
+
+    /// Set the info bar screen to the provided image, for devices with an info bar
+    ///
+    /// The image must match [Kind::info_bar_size], devices without an info bar
+    /// return [Error::NoInfoBar]. The image is written immediately, frames started
+    /// with [StreamDeck::begin_frame] only buffer key images.
+    pub fn set_info_bar_image(&mut self, image: DynamicImage) -> Result<(), Error> {
+        let (w, h) = self.kind.info_bar_size().ok_or(Error::NoInfoBar)?;
+
+        let rotation = self.orientation.then(&self.kind.image_rotation());
+        let image = apply_transform(image, rotation, self.kind.image_mirror());
+        let data = image.into_rgb8().into_vec();
+        if data.len() != w * h * 3 {
+            return Err(Error::InvalidImageSize {
+                got: data.len(),
+                expected: w * h * 3,
+            });
+        }
+        let image = encode_jpeg(&data, w, h)?;
+
+        // Info bar reports share the v2 key image header layout, without a key index
+        let mut buf = vec![0u8; self.kind.image_report_len()];
+        let hdrlen = self.kind.image_report_header_len();
+        let chunk_size = buf.len() - hdrlen;
+        let mut device = self.device();
+
+        for (page, chunk) in image.chunks(chunk_size).enumerate() {
+            let is_last = (page + 1) * chunk_size >= image.len();
+            buf[..4].copy_from_slice(&[0x02, 0x0b, 0x00, if is_last { 1 } else { 0 }]);
+            buf[4..6].copy_from_slice(&(chunk.len() as u16).to_le_bytes());
+            buf[6..8].copy_from_slice(&(page as u16).to_le_bytes());
+            buf[hdrlen..hdrlen + chunk.len()].copy_from_slice(chunk);
+            buf[hdrlen + chunk.len()..].fill(0);
+
+            device.write(&buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the colour of a touch point, see [Kind::touch_points]
+    ///
+    /// Touch points are indexed from 0, returning [Error::InvalidKeyIndex] where out of range.
+    pub fn set_touch_point_colour(
+        &mut self,
+        index: u8,
+        colour: impl Into<Colour>,
+    ) -> Result<(), Error> {
+        if index >= self.kind.touch_points() {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        let colour = colour.into();
+        let mut cmd = [0u8; 32];
+        cmd[..6].copy_from_slice(&[
+            0x03,
+            0x06,
+            self.kind.keys() + index,
+            colour.r,
+            colour.g,
+            colour.b,
+        ]);
+        self.device().send_feature_report(&cmd)?;
+
+        Ok(())
+    }
+
+    /// Set multiple buttons from cells of a sprite sheet image
+    ///
+    /// The sheet is divided into a grid of `cols` by `rows` equally sized cells, numbered
+    /// left-to-right then top-to-bottom, and `mapping` lists `(cell, key)` pairs to upload.
+    /// Each cell is resized to the device image size. The grid must contain at least one cell
+    /// and each cell at least one pixel, otherwise `Error::InvalidSheetGrid` is returned. All
+    /// cell and key indices are validated before any buttons are written.
+    pub fn set_buttons_from_sheet(
+        &mut self,
+        sheet: &DynamicImage,
+        cols: u32,
+        rows: u32,
+        mapping: &[(usize, u8)],
+    ) -> Result<(), Error> {
+        let (width, height) = (sheet.width(), sheet.height());
+        let cells = match cols.checked_mul(rows) {
+            Some(n) if n > 0 && cols <= width && rows <= height => n as usize,
+            _ => {
+                return Err(Error::InvalidSheetGrid {
+                    cols,
+                    rows,
+                    width,
+                    height,
+                })
+            }
+        };
+        if mapping.iter().any(|(cell, _)| *cell >= cells) {
+            return Err(Error::InvalidCellIndex);
+        }
+        for (_, key) in mapping {
+            self.kind.translate_key_index(*key)?;
+        }
+
+        let (w, h) = (width / cols, height / rows);
+        let (x, y) = self.kind.image_size();
+
+        for (cell, key) in mapping {
+            let (col, row) = (*cell as u32 % cols, *cell as u32 / cols);
+            let image = sheet.crop_imm(col * w, row * h, w, h).resize_exact(
+                x as u32,
+                y as u32,
+                FilterType::Gaussian,
+            );
+
+            self.set_button_image(*key, image)?;
+        }
+
+        Ok(())
     }
 
     /// Sets a button to the provided text.
     /// Will break text over \n linebreaks
+    #[cfg(feature = "text")]
     pub fn set_button_text(
         &mut self,
         key: u8,
@@ -328,7 +1272,7 @@ impl StreamDeck {
         match pos {
             TextPosition::Absolute { x, y } => {
                 let mut y = *y;
-                text.to_string().split("\n").for_each(|txt| {
+                text.split('\n').for_each(|txt| {
                     draw_text_mut(&mut image, colour, *x, y, opts.scale, font, txt);
                     y += (opts.scale.y * opts.line_height).round() as i32;
                 });
@@ -339,19 +1283,71 @@ impl StreamDeck {
     }
 
     ///  Set a button to the provided image file
+    ///
+    /// If a missing image placeholder is set (see [StreamDeck::set_missing_image_placeholder])
+    /// this is shown where the file cannot be loaded, rather than returning an error.
     pub fn set_button_file(
         &mut self,
         key: u8,
         image: &str,
         opts: &ImageOptions,
     ) -> Result<(), Error> {
-        self.write_button_image(key, &self.load_image(image, opts)?)
+        let loaded = match (self.load_image(image, opts), &self.missing_placeholder) {
+            (Ok(i), _) => i,
+            (Err(e @ Error::Io(_)), Some(style)) | (Err(e @ Error::Image(_)), Some(style)) => {
+                warn!(
+                    "Failed to load '{}' for key {}, using placeholder: {}",
+                    image, key, e
+                );
+                self.encode_image(render_placeholder(self.kind, style))?
+            }
+            (Err(e), _) => return Err(e),
+        };
+
+        self.write_button_image(key, &loaded)?;
+        self.set_content(
+            key,
+            Some(KeyContent::Image {
+                path: image.to_string(),
+                options: opts.clone(),
+            }),
+        );
+
+        Ok(())
+    }
+
+    /// Set a placeholder to be shown when image files fail to load
+    ///
+    /// Where set, [StreamDeck::set_button_file] logs a warning and shows the placeholder
+    /// for files that are missing or cannot be decoded. Disabled (`None`) by default,
+    /// so load failures are returned as errors.
+    pub fn set_missing_image_placeholder(&mut self, style: Option<PlaceholderStyle>) {
+        self.missing_placeholder = style;
+    }
+
+    /// Set the default image options used by [StreamDeck::set_button_file_default]
+    pub fn set_default_image_options(&mut self, opts: ImageOptions) {
+        self.image_options = opts;
+    }
+
+    /// Fetch the default image options
+    pub fn default_image_options(&self) -> &ImageOptions {
+        &self.image_options
+    }
+
+    /// Set a button to the provided image file using the default image options
+    ///
+    /// Use [StreamDeck::set_button_file] to override the defaults for a single call.
+    pub fn set_button_file_default(&mut self, key: u8, image: &str) -> Result<(), Error> {
+        let opts = self.image_options.clone();
+        self.set_button_file(key, image, &opts)
     }
 
     /// Load an image file into the device specific representation
     pub fn load_image(&self, image: &str, opts: &ImageOptions) -> Result<DeviceImage, Error> {
+        self.check_display()?;
         let (x, y) = self.kind.image_size();
-        let rotate = self.kind.image_rotation();
+        let rotate = self.orientation.then(&self.kind.image_rotation());
         let mirror = self.kind.image_mirror();
 
         let image = images::load_image(
@@ -366,126 +1362,344 @@ impl StreamDeck {
         self.convert_image(image)
     }
 
-    /// Transforms a key from zero-indexed left-to-right into the device-correct coordinate system
-    fn translate_key_index(&self, key: u8) -> Result<u8, Error> {
-        if key > self.kind.keys() {
-            return Err(Error::InvalidKeyIndex);
+    /// Resize, transform and encode an image into the device specific representation
+    ///
+    /// This performs all of the image processing up front, so the result can be uploaded
+    /// with [StreamDeck::write_button_image] at the cost of the USB transfer alone.
+    pub fn prepare_image(
+        &self,
+        image: &DynamicImage,
+        opts: &ImageOptions,
+    ) -> Result<DeviceImage, Error> {
+        let (x, y) = self.kind.image_size();
+        let rotate = self.orientation.then(&self.kind.image_rotation());
+
+        let image = images::process_image(
+            image.clone(),
+            x,
+            y,
+            rotate,
+            self.kind.image_mirror(),
+            opts,
+            self.kind.image_colour_order(),
+        )?;
+        self.convert_image(image)
+    }
+
+    /// Set the maximum image payload size for each packet of an image upload
+    ///
+    /// This defaults to (and may not exceed) [Kind::max_upload_chunk_size], and must leave
+    /// room for any image header in the first packet. Smaller chunks may be more reliable
+    /// on slow or congested USB connections at the cost of upload speed.
+    /// The original Stream Deck uses fixed size packets and ignores this setting.
+    pub fn set_upload_chunk_size(&mut self, size: usize) -> Result<(), Error> {
+        if size <= self.kind.image_base().len() || size > self.kind.max_upload_chunk_size() {
+            return Err(Error::InvalidChunkSize);
+        }
+
+        self.upload_chunk_size = size;
+
+        Ok(())
+    }
+
+    /// Fetch the image upload chunk size
+    pub fn upload_chunk_size(&self) -> usize {
+        self.upload_chunk_size
+    }
+
+    /// Start buffering button image writes into a frame
+    ///
+    /// Subsequent `set_button_*` and `write_button_image` calls are held (with only the
+    /// latest image kept per key) until [StreamDeck::flush] is called, so several keys
+    /// can be updated together with less visible tearing.
+    /// Without an explicit frame, writes are sent to the device immediately.
+    pub fn begin_frame(&mut self) {
+        if self.frame.is_none() {
+            self.frame = Some(Vec::new());
         }
-        let mapped = match self.kind.key_direction() {
-            // All but the original Streamdeck already have correct coordinates
-            KeyDirection::LeftToRight => key + self.kind.key_index_offset(),
-            // The original Streamdeck uses 1-indexed right-to-left
-            KeyDirection::RightToLeft => {
-                let cols = self.kind.key_columns() as u8;
-                let col = key % cols;
-                let row = key / cols;
-                row * cols + cols - col
+    }
+
+    /// Write any buffered button images to the device and end the current frame
+    ///
+    /// When background uploads are enabled this also waits for all queued uploads
+    /// to complete, returning any error from the background writer.
+    /// While asleep images remain buffered until wake, see [StreamDeck::set_sleep].
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.sleeping {
+            return Ok(());
+        }
+
+        if let Some(frame) = self.frame.take() {
+            for (key, image) in frame {
+                self.upload_button_image(key, &image)?;
             }
-        };
-        Ok(mapped)
+        }
+
+        match &self.writer {
+            Some(w) => w.wait(),
+            None => Ok(()),
+        }
+    }
+
+    /// Flush any buffered button images and wait for background uploads to complete,
+    /// then close the device
+    ///
+    /// Dropping the device does the same, this allows errors to be handled.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.flush()
+    }
+
+    /// Enable or disable background image uploads
+    ///
+    /// When enabled, button image writes are queued to a background thread and
+    /// return immediately, with a pending upload replaced if the same key is written
+    /// again before it has started, and an upload in progress abandoned if the key is
+    /// written again before it completes. Use [StreamDeck::flush] to wait for uploads to
+    /// complete. Upload errors are reported by the next call to [StreamDeck::flush].
+    ///
+    /// Uploads are blocked while the device is in use by other calls, so input should
+    /// be read with a short timeout to keep uploads responsive.
+    ///
+    /// Disabling background uploads waits for any queued uploads to complete.
+    /// Uploads are synchronous by default.
+    pub fn set_background_uploads(&mut self, enabled: bool) -> Result<(), Error> {
+        match (enabled, self.writer.take()) {
+            (true, None) => {
+                self.writer = Some(BackgroundWriter::new(self.device.clone(), self.kind));
+                Ok(())
+            }
+            (true, Some(w)) => {
+                self.writer = Some(w);
+                Ok(())
+            }
+            (false, Some(w)) => w.wait(),
+            (false, None) => Ok(()),
+        }
+    }
+
+    /// Run the provided closure with button image writes buffered into a single frame,
+    /// flushing the frame once the closure completes successfully
+    ///
+    /// If the closure fails its writes are discarded, while images already buffered (by an
+    /// open frame or while asleep) are kept.
+    pub fn frame<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut StreamDeck) -> Result<(), Error>,
+    {
+        let buffered = self.frame.clone();
+        self.begin_frame();
+
+        if let Err(e) = f(self) {
+            self.frame = buffered;
+            return Err(e);
+        }
+
+        self.flush()
     }
 
     /// Writes an image to a button
     /// Image at this point in correct dimensions and in device native colour order.
+    ///
+    /// If a frame has been started with [StreamDeck::begin_frame] the image is
+    /// buffered until the frame is flushed. Writes to keys claimed by other owners
+    /// are rejected, see [StreamDeck::claim_key].
     pub fn write_button_image(&mut self, key: u8, image: &DeviceImage) -> Result<(), Error> {
-        let image = &image.data;
-        let key = self.translate_key_index(key)?;
+        self.check_display()?;
+        // Check the key index now so buffered writes do not fail on flush
+        self.oriented_key_index(key)?;
+        self.key_owners.check(key, self.claim_token)?;
+        if let Some(i) = self.oriented_images.get_mut(key as usize) {
+            *i = None;
+        }
 
-        let mut buf = vec![0u8; self.kind.image_report_len()];
-        let base = self.kind.image_base();
-        let hdrlen = self.kind.image_report_header_len();
+        // Writes while asleep are always buffered, even where the frame has been discarded
+        if self.sleeping {
+            self.begin_frame();
+        }
 
-        match self.kind {
-            Kind::Original => {
-                // Original Streamdeck uses static lengths, not the dynamically sized protocol on the
-                // later versions. First packet contains the initial 7749 bytes.
-                self.write_image_header(&mut buf, key, 1, false, 0);
-                let start = hdrlen + base.len();
-                buf[hdrlen..start].copy_from_slice(base);
-                buf[start..start + 7749].copy_from_slice(&image[0..7749]);
-                self.device.write(&buf)?;
-
-                // Second packet contains the last 7803 bytes
-                self.write_image_header(&mut buf, key, 2, true, 0);
-                buf[hdrlen..hdrlen + 7803].copy_from_slice(&image[7749..15552]);
-                self.device.write(&buf)?;
+        if let Some(frame) = &mut self.frame {
+            frame.retain(|(k, _)| *k != key);
+            frame.push((key, image.clone()));
+        } else {
+            self.upload_button_image(key, image)?;
+        }
 
-                Ok(())
+        if let Some(i) = self
+            .retained_images
+            .as_mut()
+            .and_then(|r| r.get_mut(key as usize))
+        {
+            *i = Some(image.clone());
+        }
+        self.set_content(key, None);
+
+        Ok(())
+    }
+
+    /// Record the content of a key for layout export
+    fn set_content(&mut self, key: u8, content: Option<KeyContent>) {
+        if let Some(c) = self.contents.get_mut(key as usize) {
+            *c = content;
+        }
+    }
+
+    /// Uploads an image to a button, or queues it when background uploads are enabled
+    fn upload_button_image(&mut self, key: u8, image: &DeviceImage) -> Result<(), Error> {
+        let key = self.oriented_key_index(key)?;
+
+        if let Some(w) = &self.writer {
+            w.queue(key, image.clone(), self.upload_chunk_size);
+            return Ok(());
+        }
+
+        let chunk_size = self.upload_chunk_size;
+        upload_image(
+            &mut **self.device(),
+            self.kind,
+            chunk_size,
+            key,
+            &image.data,
+            &|| false,
+        )
+    }
+}
+
+impl Drop for StreamDeck {
+    /// Buffered images are written and background uploads completed before the device
+    /// is closed, so the final state is shown. Images buffered while asleep are discarded.
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("Failed to flush pending uploads on close: {}", e);
+        }
+    }
+}
+
+/// Uploads an image to the provided device key index (see [Kind::translate_key_index])
+///
+/// `superseded` is checked before each packet after the first, with the upload abandoned
+/// if this returns true. Devices only display an image once the last packet is received,
+/// so the previous image remains until the superseding upload completes.
+pub(crate) fn upload_image(
+    device: &mut dyn DeckTransport,
+    kind: Kind,
+    chunk_size: usize,
+    key: u8,
+    image: &[u8],
+    superseded: &dyn Fn() -> bool,
+) -> Result<(), Error> {
+    let mut buf = vec![0u8; kind.image_report_len()];
+    let base = kind.image_base();
+    let hdrlen = kind.image_report_header_len();
+
+    match kind {
+        Kind::Original => {
+            if image.len() != 15552 {
+                return Err(Error::InvalidImageSize {
+                    got: image.len(),
+                    expected: 15552,
+                });
             }
 
-            _ => {
-                let mut sequence = 0;
-                let mut offset = 0;
-                let maxdatalen = buf.len() - hdrlen;
-
-                while offset < image.len() {
-                    let mut take = (image.len() - offset).min(maxdatalen);
-                    let mut start = hdrlen;
-
-                    if sequence == 0 && !base.is_empty() {
-                        trace!("outputting base");
-                        buf[start..start + base.len()].copy_from_slice(base);
-                        // Recalculate take with the smaller room
-                        take = (image.len() - offset).min(maxdatalen - base.len());
-                        start += base.len();
-                    }
+            // Original Streamdeck uses static lengths, not the dynamically sized protocol on the
+            // later versions. First packet contains the initial 7749 bytes.
+            write_image_header(kind, &mut buf, key, 1, false, 0);
+            let start = hdrlen + base.len();
+            buf[hdrlen..start].copy_from_slice(base);
+            buf[start..start + 7749].copy_from_slice(&image[0..7749]);
+            device.write(&buf)?;
+
+            if superseded() {
+                debug!("abandoning superseded upload to key {}", key);
+                return Ok(());
+            }
 
-                    let is_last = take == image.len() - offset;
-                    self.write_image_header(&mut buf, key, sequence, is_last, take);
-                    buf[start..start + take].copy_from_slice(&image[offset..offset + take]);
-
-                    trace!(
-                        "outputting image chunk [{}..{}[ in [{}..{}[, sequence {}{}",
-                        offset,
-                        offset + take,
-                        start,
-                        start + take,
-                        sequence,
-                        if is_last { " (last)" } else { "" },
-                    );
-                    self.device.write(&buf)?;
-
-                    sequence += 1;
-                    offset += take;
+            // Second packet contains the last 7803 bytes
+            write_image_header(kind, &mut buf, key, 2, true, 0);
+            buf[hdrlen..hdrlen + 7803].copy_from_slice(&image[7749..15552]);
+            device.write(&buf)?;
+
+            Ok(())
+        }
+
+        _ => {
+            let mut sequence = 0;
+            let mut offset = 0;
+            let maxdatalen = chunk_size;
+
+            while offset < image.len() {
+                if sequence > 0 && superseded() {
+                    debug!("abandoning superseded upload to key {}", key);
+                    return Ok(());
                 }
-                Ok(())
+
+                let mut take = (image.len() - offset).min(maxdatalen);
+                let mut start = hdrlen;
+
+                if sequence == 0 && !base.is_empty() {
+                    trace!("outputting base");
+                    buf[start..start + base.len()].copy_from_slice(base);
+                    // Recalculate take with the smaller room
+                    take = (image.len() - offset).min(maxdatalen - base.len());
+                    start += base.len();
+                }
+
+                let is_last = take == image.len() - offset;
+                write_image_header(kind, &mut buf, key, sequence, is_last, take);
+                buf[start..start + take].copy_from_slice(&image[offset..offset + take]);
+
+                trace!(
+                    "outputting image chunk [{}..{}[ in [{}..{}[, sequence {}{}",
+                    offset,
+                    offset + take,
+                    start,
+                    start + take,
+                    sequence,
+                    if is_last { " (last)" } else { "" },
+                );
+                device.write(&buf)?;
+
+                sequence += 1;
+                offset += take;
             }
+            Ok(())
         }
     }
+}
 
-    /// Writes the image report header to the given buffer
-    fn write_image_header(
-        &self,
-        buf: &mut [u8],
-        key: u8,
-        sequence: u16,
-        is_last: bool,
-        payload_len: usize,
-    ) {
-        if self.kind.is_v2() {
-            buf[0] = 0x02;
-            buf[1] = 0x07;
-            buf[2] = key;
-            buf[3] = if is_last { 1 } else { 0 };
-            buf[4..6].copy_from_slice(&(payload_len as u16).to_le_bytes());
-            buf[6..8].copy_from_slice(&sequence.to_le_bytes());
-        } else {
-            buf[0] = 0x02;
-            buf[1] = 0x01;
-            buf[2..4].copy_from_slice(&sequence.to_le_bytes());
-            buf[4] = if is_last { 1 } else { 0 };
-            buf[5] = key;
-        }
+/// Writes the image report header to the given buffer
+fn write_image_header(
+    kind: Kind,
+    buf: &mut [u8],
+    key: u8,
+    sequence: u16,
+    is_last: bool,
+    payload_len: usize,
+) {
+    if kind.is_v2() {
+        buf[0] = 0x02;
+        buf[1] = 0x07;
+        buf[2] = key;
+        buf[3] = if is_last { 1 } else { 0 };
+        buf[4..6].copy_from_slice(&(payload_len as u16).to_le_bytes());
+        buf[6..8].copy_from_slice(&sequence.to_le_bytes());
+    } else {
+        buf[0] = 0x02;
+        buf[1] = 0x01;
+        buf[2..4].copy_from_slice(&sequence.to_le_bytes());
+        buf[4] = if is_last { 1 } else { 0 };
+        buf[5] = key;
     }
 }
 
 /// TextPosition is how to position text via set_button_text
+#[cfg(feature = "text")]
 pub enum TextPosition {
     /// Absolute positioning
     Absolute { x: i32, y: i32 },
 }
 
 /// Text Options provide values for text buttons
+#[cfg(feature = "text")]
 pub struct TextOptions {
     foreground: Colour,
     background: Colour,
@@ -493,6 +1707,7 @@ pub struct TextOptions {
     line_height: f32,
 }
 
+#[cfg(feature = "text")]
 impl TextOptions {
     pub fn new(foreground: Colour, background: Colour, scale: PxScale, line_height: f32) -> Self {
         TextOptions {
@@ -504,22 +1719,1195 @@ impl TextOptions {
     }
 }
 
+#[cfg(feature = "text")]
 impl Default for TextOptions {
     /// default is white text on a black background, with 15 pixel high text
     /// and 1.1x the line height.
     fn default() -> Self {
         TextOptions {
-            foreground: Colour::from_str("FFFFFF").unwrap(),
-            background: Colour::from_str("000000").unwrap(),
+            foreground: Colour::WHITE,
+            background: Colour::BLACK,
             scale: PxScale { x: 15.0, y: 15.0 },
             line_height: 1.1,
         }
     }
 }
 
+/// Map errors opening a device to more specific variants
+#[cfg(feature = "hidapi")]
+fn open_error(e: HidError) -> Error {
+    let message = match &e {
+        HidError::HidApiError { message } => message.clone(),
+        _ => return Error::Hid(e),
+    };
+
+    let lower = message.to_lowercase();
+    if ["busy", "exclusive", "sharing violation", "in use"]
+        .iter()
+        .any(|s| lower.contains(s))
+    {
+        Error::Busy
+    } else if [
+        "permission denied",
+        "access denied",
+        "access is denied",
+        "kioreturnnotpermitted",
+        "kioreturnnotprivileged",
+    ]
+    .iter()
+    .any(|s| lower.contains(s))
+    {
+        Error::PermissionDenied(message)
+    } else {
+        Error::OpenFailed(message)
+    }
+}
+
+/// Check whether a device product string identifies a device in bootloader (firmware update) mode
+///
+/// Bootloader pids are not documented, so devices are identified by product string.
+#[cfg(feature = "hidapi")]
+fn is_bootloader(product: Option<&str>) -> bool {
+    product
+        .map(|p| p.to_lowercase())
+        .is_some_and(|p| p.contains("bootloader") || p.contains("dfu"))
+}
+
+/// Find the single serial matching the provided prefix, preferring an exact match
+#[cfg(feature = "hidapi")]
+fn match_serial<'a>(
+    serials: impl Iterator<Item = &'a str>,
+    prefix: &str,
+) -> Result<Option<String>, Error> {
+    let mut matches: Vec<String> = Vec::new();
+
+    for s in serials.filter(|s| s.starts_with(prefix)) {
+        if s == prefix {
+            return Ok(Some(s.to_string()));
+        }
+        // Devices may enumerate more than one interface
+        if !matches.iter().any(|m| m == s) {
+            matches.push(s.to_string());
+        }
+    }
+
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(matches.pop()),
+        _ => Err(Error::AmbiguousSerial(matches)),
+    }
+}
+
+/// Filter device information to the provided kind
+#[cfg(feature = "hidapi")]
+fn filter_kind(devices: impl IntoIterator<Item = DeviceInfo>, kind: Kind) -> Vec<DeviceInfo> {
+    devices
+        .into_iter()
+        .filter(|d| Kind::from_pid(d.pid) == Some(kind))
+        .collect()
+}
+
+/// Resolve the handler for an input report (with the report ID already stripped)
+/// from the device input types, see [Kind::input_types]
+fn dispatch_input(kind: Kind, strict: bool, report: &[u8]) -> Result<InputType, Error> {
+    let types = kind.input_types();
+
+    // Devices without input types only report keys
+    let report_type = match report.first() {
+        Some(t) if !types.is_empty() => *t,
+        _ => return Ok(InputType::Keys),
+    };
+
+    match types.iter().find(|(t, _)| *t == report_type) {
+        Some((_, handler)) => Ok(*handler),
+        None if strict => Err(Error::UnsupportedInput { report_type }),
+        None => {
+            debug!(
+                "Decoding unrecognised input type 0x{:02x} as keys",
+                report_type
+            );
+            Ok(InputType::Keys)
+        }
+    }
+}
+
+/// Decode key states, followed by any touch point states, from an input report
+/// (with the report ID already stripped)
+fn decode_buttons(kind: Kind, offset: usize, report: &[u8]) -> Result<Vec<u8>, Error> {
+    let keys = kind.keys() as usize;
+    let buttons = kind.buttons() as usize;
+
+    if report.len() < offset + buttons {
+        debug!(
+            "Short input report ({} bytes): {:02x?}",
+            report.len(),
+            report
+        );
+        return Err(Error::InvalidReport);
+    }
+
+    let mut out = vec![0u8; buttons];
+    match kind.key_direction() {
+        KeyDirection::RightToLeft => {
+            for (i, val) in out[..keys].iter_mut().enumerate() {
+                // In right-to-left mode (original Streamdeck) the first key has index 1
+                let index = kind.translate_key_index(i as u8)? as usize - 1;
+                *val = report[offset + index];
+            }
+        }
+        KeyDirection::LeftToRight => {
+            out[..keys].copy_from_slice(&report[offset..offset + keys]);
+        }
+    }
+    out[keys..].copy_from_slice(&report[offset + keys..offset + buttons]);
+
+    Ok(out)
+}
+
+/// Decode dial input from a dial report (with the report ID already stripped)
+///
+/// Dial reports contain the input type and length header, followed by the dial
+/// input type (press or rotation) and a value for each dial. A dial turned while
+/// pressed is sent as separate press and rotation reports.
+fn decode_dials(kind: Kind, report: &[u8]) -> Result<DialInput, Error> {
+    let dials = kind.dials() as usize;
+
+    let values = match report.get(4..4 + dials) {
+        Some(v) => v,
+        None => {
+            debug!(
+                "Short dial report ({} bytes): {:02x?}",
+                report.len(),
+                report
+            );
+            return Err(Error::InvalidReport);
+        }
+    };
+
+    match report[3] {
+        0x00 => Ok(DialInput::Pressed(values.iter().map(|v| *v != 0).collect())),
+        0x01 => Ok(DialInput::Turned(values.iter().map(|v| *v as i8).collect())),
+        t => {
+            debug!("Unrecognised dial input type 0x{:02x}", t);
+            Err(Error::InvalidReport)
+        }
+    }
+}
+
 // Convert RGB image data to BGR
-fn rgb_to_bgr(data: &mut Vec<u8>) {
+fn rgb_to_bgr(data: &mut [u8]) {
     for chunk in data.chunks_exact_mut(3) {
         chunk.swap(0, 2);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    use image::{ImageBuffer, Rgb};
+
+    use super::*;
+
+    fn mock_deck(kind: Kind) -> (StreamDeck, MockTransport) {
+        let mock = MockTransport::new();
+        (StreamDeck::with_transport(mock.clone(), kind), mock)
+    }
+
+    /// Transport blocking after the first write until resumed
+    pub(crate) struct GatedTransport {
+        inner: MockTransport,
+        started: Sender<()>,
+        resume: Option<Receiver<()>>,
+    }
+
+    impl GatedTransport {
+        /// Wrap a mock, returning the transport along with the started and resume channels
+        pub(crate) fn new(inner: MockTransport) -> (GatedTransport, Receiver<()>, Sender<()>) {
+            let (started_tx, started) = channel();
+            let (resume, resume_rx) = channel();
+            let transport = GatedTransport {
+                inner,
+                started: started_tx,
+                resume: Some(resume_rx),
+            };
+            (transport, started, resume)
+        }
+    }
+
+    impl DeckTransport for GatedTransport {
+        fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+            let n = self.inner.write(data)?;
+            if let Some(resume) = self.resume.take() {
+                self.started.send(()).unwrap();
+                resume.recv().unwrap();
+            }
+            Ok(n)
+        }
+
+        fn read(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> Result<usize, Error> {
+            self.inner.read(buf, timeout)
+        }
+
+        fn send_feature_report(&mut self, data: &[u8]) -> Result<(), Error> {
+            self.inner.send_feature_report(data)
+        }
+
+        fn get_feature_report(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.inner.get_feature_report(buf)
+        }
+
+        fn set_blocking_mode(&mut self, blocking: bool) -> Result<(), Error> {
+            self.inner.set_blocking_mode(blocking)
+        }
+    }
+
+    #[test]
+    fn flush_on_drop() {
+        let kind = Kind::Xl;
+        let expected = {
+            let (mut deck, mock) = mock_deck(kind);
+            for key in 0..4 {
+                deck.set_button_rgb(key, &Colour::RED).unwrap();
+            }
+            mock.written_packets()
+        };
+
+        // Buffered frames and background uploads are written before the device is closed
+        let (mut deck, mock) = mock_deck(kind);
+        deck.set_background_uploads(true).unwrap();
+        deck.begin_frame();
+        for key in 0..4 {
+            deck.set_button_rgb(key, &Colour::RED).unwrap();
+        }
+        drop(deck);
+        assert_eq!(mock.written_packets(), expected);
+
+        let (mut deck, mock) = mock_deck(kind);
+        deck.set_background_uploads(true).unwrap();
+        for key in 0..4 {
+            deck.set_button_rgb(key, &Colour::RED).unwrap();
+        }
+        deck.close().unwrap();
+        assert_eq!(mock.written_packets(), expected);
+    }
+
+    #[test]
+    fn background_uploads() {
+        let mock = MockTransport::new();
+        let (transport, started, resume) = GatedTransport::new(mock.clone());
+        let mut deck = StreamDeck::with_transport(transport, Kind::Mini);
+        deck.set_background_uploads(true).unwrap();
+
+        // Hold the first upload on the device so the rest queue up behind it
+        deck.set_button_rgb(0, &Colour::RED).unwrap();
+        started.recv().unwrap();
+
+        deck.set_button_rgb(0, &Colour::GREEN).unwrap();
+        deck.set_button_rgb(0, &Colour::BLUE).unwrap();
+        deck.set_button_rgb(1, &Colour::WHITE).unwrap();
+        assert!(matches!(
+            deck.set_button_rgb(6, &Colour::RED),
+            Err(Error::InvalidKeyIndex)
+        ));
+
+        resume.send(()).unwrap();
+        deck.flush().unwrap();
+
+        // Superseded uploads are skipped, the upload in progress is not
+        let first_packet = |key: u8, colour: &Colour| {
+            let (mut deck, mock) = mock_deck(Kind::Mini);
+            deck.set_button_rgb(key, colour).unwrap();
+            mock.written_packets()[0].clone()
+        };
+        let uploads: Vec<Vec<u8>> = mock
+            .written_packets()
+            .into_iter()
+            .filter(|p| p[2..4] == [0, 0])
+            .collect();
+        let expected = vec![
+            first_packet(0, &Colour::RED),
+            first_packet(0, &Colour::BLUE),
+            first_packet(1, &Colour::WHITE),
+        ];
+        assert_eq!(uploads, expected);
+
+        deck.set_background_uploads(false).unwrap();
+    }
+
+    #[test]
+    fn upside_down_orientation() {
+        let (mut deck, mock) = mock_deck(Kind::Mini);
+        assert!(matches!(
+            deck.set_orientation(Rotation::Rot90),
+            Err(Error::UnsupportedOrientation)
+        ));
+        deck.set_orientation(Rotation::Rot180).unwrap();
+
+        // Images are rotated and written to the opposite key
+        let (w, h) = Kind::Mini.image_size();
+        let mut image = ImageBuffer::from_pixel(w as u32, h as u32, Rgb([0u8, 0, 0]));
+        image.put_pixel(0, 0, Rgb([255, 255, 255]));
+        let image = DynamicImage::ImageRgb8(image);
+
+        deck.set_button_image(1, image.clone()).unwrap();
+
+        let (mut upright, upright_mock) = mock_deck(Kind::Mini);
+        upright.set_button_image(4, image.rotate180()).unwrap();
+        assert_eq!(mock.written_packets(), upright_mock.written_packets());
+
+        assert!(matches!(
+            deck.set_button_rgb(6, &Colour::RED),
+            Err(Error::InvalidKeyIndex)
+        ));
+
+        // Key presses are remapped
+        mock.push_input(&[0x01, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            deck.read_buttons(None)
+                .unwrap()
+                .pressed_indices()
+                .collect::<Vec<_>>(),
+            vec![5]
+        );
+    }
+
+    #[test]
+    fn prepared_images() {
+        let kind = Kind::OriginalV2;
+        let image = image::open("./icons/power.png").unwrap();
+        let opts = ImageOptions::default();
+
+        let (mut deck, mock) = mock_deck(kind);
+        let prepared = deck.prepare_image(&image, &opts).unwrap();
+        deck.write_button_image(0, &prepared).unwrap();
+
+        // Matches loading the same file
+        let (mut loaded, loaded_mock) = mock_deck(kind);
+        loaded
+            .set_button_file(0, "./icons/power.png", &opts)
+            .unwrap();
+        assert_eq!(mock.written_packets(), loaded_mock.written_packets());
+    }
+
+    #[test]
+    fn strict_input_types() {
+        let (mut deck, mock) = mock_deck(Kind::Xl);
+        let mut report = vec![0u8; Kind::Xl.input_report_size()];
+        report[..2].copy_from_slice(&[0x01, 0x02]);
+        report[4] = 1;
+
+        mock.push_input(&report);
+        assert!(deck.read_buttons(None).unwrap().is_pressed(0));
+
+        deck.set_strict_input(true);
+        mock.push_input(&report);
+        assert!(matches!(
+            deck.read_buttons(None),
+            Err(Error::UnsupportedInput { report_type: 0x02 })
+        ));
+
+        report[1] = 0x00;
+        mock.push_input(&report);
+        assert!(deck.read_buttons(None).unwrap().is_pressed(0));
+    }
+
+    #[test]
+    fn input_dispatch() {
+        for kind in (0..=u16::MAX).filter_map(Kind::from_pid) {
+            let mut report = vec![0u8; kind.input_report_size() - 1];
+            assert_eq!(
+                dispatch_input(kind, true, &report).unwrap(),
+                InputType::Keys
+            );
+            assert_eq!(dispatch_input(kind, true, &[]).unwrap(), InputType::Keys);
+
+            // Unknown types are only rejected for devices with typed reports
+            report[0] = 0x02;
+            assert_eq!(
+                dispatch_input(kind, false, &report).unwrap(),
+                InputType::Keys
+            );
+            match kind.is_v2() {
+                true => assert!(matches!(
+                    dispatch_input(kind, true, &report),
+                    Err(Error::UnsupportedInput { report_type: 0x02 })
+                )),
+                false => assert_eq!(
+                    dispatch_input(kind, true, &report).unwrap(),
+                    InputType::Keys
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn pedal_without_display() {
+        let (mut deck, mock) = mock_deck(Kind::Pedal);
+
+        assert!(matches!(
+            deck.set_button_rgb(0, &Colour::RED),
+            Err(Error::NoDisplay)
+        ));
+        assert!(matches!(
+            deck.set_button_file(1, "./icons/power.png", &ImageOptions::default()),
+            Err(Error::NoDisplay)
+        ));
+        assert!(matches!(
+            deck.write_button_image(2, &DeviceImage::from(vec![0u8; 16])),
+            Err(Error::NoDisplay)
+        ));
+        assert!(matches!(deck.set_brightness(50), Err(Error::NoDisplay)));
+        assert!(mock.written_packets().is_empty());
+        assert!(mock.sent_feature_reports().is_empty());
+
+        // Pedals are read as keys
+        mock.push_input(&[0x01, 0x00, 0x03, 0x00, 0, 1, 0]);
+        let mut manager = InputManager::new(&mut deck);
+        assert_eq!(
+            manager.handle_input(None).unwrap(),
+            vec![InputEvent::Button {
+                index: 1,
+                action: ButtonAction::Pressed
+            }]
+        );
+    }
+
+    #[test]
+    fn neo_touch_points_and_info_bar() {
+        let kind = Kind::Neo;
+        let (mut deck, mock) = mock_deck(kind);
+
+        // Touch points follow the keys in input reports
+        let mut report = vec![0u8; kind.input_report_size()];
+        report[0] = 0x01;
+        report[4] = 1;
+        report[4 + 9] = 1;
+        mock.push_input(&report);
+        assert_eq!(
+            deck.read_buttons_raw(None).unwrap(),
+            [1, 0, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+
+        // and are not remapped by orientation
+        deck.set_orientation(Rotation::Rot180).unwrap();
+        mock.push_input(&report);
+        let mut manager = InputManager::new(&mut deck);
+        assert_eq!(
+            manager.handle_input(None).unwrap(),
+            vec![
+                InputEvent::Button {
+                    index: 7,
+                    action: ButtonAction::Pressed
+                },
+                InputEvent::Button {
+                    index: 9,
+                    action: ButtonAction::Pressed
+                }
+            ]
+        );
+        drop(manager);
+        assert_eq!(deck.physical_key_index(9).unwrap(), 9);
+        assert!(deck.physical_key_index(10).is_err());
+
+        deck.set_touch_point_colour(1, Colour::RED).unwrap();
+        assert_eq!(
+            mock.sent_feature_reports().last().unwrap()[..6],
+            [0x03, 0x06, 9, 0xff, 0x00, 0x00]
+        );
+        assert!(matches!(
+            deck.set_touch_point_colour(2, Colour::RED),
+            Err(Error::InvalidKeyIndex)
+        ));
+
+        let (w, h) = kind.info_bar_size().unwrap();
+        let image = DynamicImage::new_rgb8(w as u32, h as u32);
+        deck.set_info_bar_image(image).unwrap();
+        let packets = mock.written_packets();
+        assert!(packets.iter().all(|p| p[..3] == [0x02, 0x0b, 0x00]));
+        assert_eq!(packets.last().unwrap()[3], 1);
+
+        assert!(matches!(
+            deck.set_info_bar_image(DynamicImage::new_rgb8(96, 96)),
+            Err(Error::InvalidImageSize { .. })
+        ));
+        let (mut xl, _) = mock_deck(Kind::Xl);
+        assert!(matches!(
+            xl.set_info_bar_image(DynamicImage::new_rgb8(248, 58)),
+            Err(Error::NoInfoBar)
+        ));
+    }
+
+    #[test]
+    fn studio_dials() {
+        let kind = Kind::Studio;
+        let (mut deck, mock) = mock_deck(kind);
+
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x00, 0x00, 0x01]);
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x01, 0xfe, 0x00]);
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x00, 0x00, 0x00]);
+        let mut report = vec![0u8; kind.input_report_size()];
+        report[0] = 0x01;
+        report[4 + 20] = 1;
+        mock.push_input(&report);
+
+        assert_eq!(deck.dials(), 2);
+
+        let mut manager = InputManager::new(&mut deck);
+        let mut events = Vec::new();
+        for _ in 0..4 {
+            events.extend(manager.handle_input(None).unwrap());
+        }
+        assert_eq!(
+            events,
+            vec![
+                InputEvent::Dial {
+                    index: 1,
+                    action: DialAction::Pressed
+                },
+                InputEvent::Dial {
+                    index: 0,
+                    action: DialAction::Turned(-2)
+                },
+                InputEvent::Dial {
+                    index: 1,
+                    action: DialAction::Released
+                },
+                InputEvent::Button {
+                    index: 20,
+                    action: ButtonAction::Pressed
+                },
+            ]
+        );
+
+        drop(manager);
+
+        // Dial reports are skipped when reading buttons, and short reports rejected
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x01, 0x01, 0x00]);
+        assert!(matches!(deck.read_buttons(None), Err(Error::NoData)));
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x01, 0x01, 0x00]);
+        mock.push_input(&report);
+        assert!(deck.read_buttons(None).unwrap().is_pressed(20));
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x01]);
+        assert!(matches!(deck.read_buttons(None), Err(Error::InvalidReport)));
+    }
+
+    #[test]
+    fn dial_turn_decoding() {
+        // Turn deltas are two's complement, positive clockwise
+        for (raw, steps) in [(0x01, 1), (0xff, -1), (0x7f, 127), (0x80, -128), (0x00, 0)] {
+            assert_eq!(
+                decode_dials(Kind::Studio, &[0x03, 0x05, 0x00, 0x01, raw, raw]).unwrap(),
+                DialInput::Turned(vec![steps, steps]),
+                "0x{:02x}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn dial_press_and_turn() {
+        // Presses and turns are sent in separate reports, the report type selecting which,
+        // so turning a held dial reports the turn without releasing it
+        let (mut deck, mock) = mock_deck(Kind::Studio);
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x00, 0x01, 0x00]);
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x01, 0x03, 0x00]);
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x00, 0x01, 0x00]);
+        mock.push_input(&[0x01, 0x03, 0x05, 0x00, 0x00, 0x00, 0x00]);
+
+        let mut manager = InputManager::new(&mut deck);
+        let mut actions = Vec::new();
+        for _ in 0..4 {
+            for event in manager.handle_input(None).unwrap() {
+                if let InputEvent::Dial { index: 0, action } = event {
+                    actions.push(action);
+                }
+            }
+        }
+        assert_eq!(
+            actions,
+            vec![
+                DialAction::Pressed,
+                DialAction::Turned(3),
+                DialAction::Released
+            ]
+        );
+    }
+
+    #[test]
+    fn reset_keep_brightness() {
+        let (mut deck, mock) = mock_deck(Kind::Xl);
+
+        deck.set_brightness(30).unwrap();
+        mock.clear();
+        deck.reset_keep_brightness().unwrap();
+
+        let reports = mock.sent_feature_reports();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0][..2], [0x03, 0x02]);
+        assert_eq!(reports[1][..3], [0x03, 0x08, 30]);
+        assert_eq!(deck.export_layout().brightness, Some(30));
+
+        // Plain resets clear the brightness
+        deck.reset().unwrap();
+        mock.clear();
+        deck.reset_keep_brightness().unwrap();
+        assert_eq!(mock.sent_feature_reports().len(), 1);
+    }
+
+    #[test]
+    fn button_rgb_colour_types() {
+        let (mut deck, mock) = mock_deck(Kind::Xl);
+        deck.set_button_rgb(0, &Colour::RED).unwrap();
+        let expected = mock.written_packets();
+
+        for colour in [Colour::RED.into(), Rgb([255, 0, 0])] {
+            mock.clear();
+            deck.set_button_rgb(0, colour).unwrap();
+            assert_eq!(mock.written_packets(), expected);
+        }
+        assert_eq!(
+            deck.export_layout().keys[0],
+            Some(KeyContent::Colour(Colour::RED))
+        );
+    }
+
+    #[test]
+    fn sleep_buffers_writes() {
+        let (mut deck, mock) = mock_deck(Kind::Xl);
+        deck.set_brightness(60).unwrap();
+        mock.clear();
+
+        deck.set_sleep(true).unwrap();
+        assert!(deck.is_sleeping());
+        assert_eq!(mock.sent_feature_reports()[0][..3], [0x03, 0x08, 0]);
+
+        // Writes, brightness and flushes are deferred
+        deck.set_button_rgb(0, &Colour::RED).unwrap();
+        deck.frame(|d| d.set_button_rgb(1, &Colour::GREEN)).unwrap();
+        deck.set_brightness(40).unwrap();
+        deck.flush().unwrap();
+        assert!(mock.written_packets().is_empty());
+        assert_eq!(mock.sent_feature_reports().len(), 1);
+
+        deck.set_sleep(false).unwrap();
+        assert!(!deck.is_sleeping());
+        assert_eq!(mock.sent_feature_reports()[1][..3], [0x03, 0x08, 40]);
+
+        let mut keys: Vec<u8> = mock.written_packets().iter().map(|p| p[2]).collect();
+        keys.dedup();
+        assert_eq!(keys, vec![0, 1]);
+    }
+
+    #[test]
+    fn sleep_failed_frame() {
+        let (mut deck, mock) = mock_deck(Kind::Xl);
+        deck.set_sleep(true).unwrap();
+
+        // A failed frame discards its own writes, not those buffered while asleep
+        deck.set_button_rgb(0, &Colour::RED).unwrap();
+        let res = deck.frame(|d| {
+            d.set_button_rgb(0, &Colour::BLUE)?;
+            d.set_button_rgb(1, &Colour::GREEN)?;
+            Err(Error::NoData)
+        });
+        assert!(matches!(res, Err(Error::NoData)));
+
+        deck.set_button_rgb(2, &Colour::WHITE).unwrap();
+        assert!(mock.written_packets().is_empty());
+
+        deck.set_sleep(false).unwrap();
+
+        let expected = MockTransport::new();
+        let mut reference = StreamDeck::with_transport(expected.clone(), Kind::Xl);
+        reference.set_button_rgb(0, &Colour::RED).unwrap();
+        reference.set_button_rgb(2, &Colour::WHITE).unwrap();
+        assert_eq!(mock.written_packets(), expected.written_packets());
+    }
+
+    #[test]
+    fn key_data_offset_override() {
+        let kind = Kind::Mk2;
+        let (mut deck, mock) = mock_deck(kind);
+
+        // Default offset, key 2 pressed
+        let mut report = vec![0u8; 1 + 3 + 15];
+        report[0] = 0x01;
+        report[1 + 3 + 2] = 1;
+        mock.push_input(&report);
+        assert_eq!(
+            deck.read_buttons(None)
+                .unwrap()
+                .pressed_indices()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        // Shifted offset
+        deck.set_key_data_offset(4);
+        let mut report = vec![0u8; 1 + 4 + 15];
+        report[0] = 0x01;
+        report[1 + 4 + 2] = 1;
+        mock.push_input(&report);
+        assert_eq!(
+            deck.read_buttons(None)
+                .unwrap()
+                .pressed_indices()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn missing_image_placeholder() {
+        let (mut deck, mock) = mock_deck(Kind::Xl);
+        let opts = ImageOptions::default();
+
+        assert!(matches!(
+            deck.set_button_file(0, "./icons/missing.png", &opts),
+            Err(Error::Io(_))
+        ));
+        assert!(mock.written_packets().is_empty());
+
+        deck.set_missing_image_placeholder(Some(PlaceholderStyle::Cross));
+        deck.set_button_file(0, "./icons/missing.png", &opts)
+            .unwrap();
+
+        let (mut expected, expected_mock) = mock_deck(Kind::Xl);
+        expected
+            .set_button_image(0, render_placeholder(Kind::Xl, &PlaceholderStyle::Cross))
+            .unwrap();
+        assert_eq!(mock.written_packets(), expected_mock.written_packets());
+
+        // Invalid keys are still reported
+        assert!(matches!(
+            deck.set_button_file(32, "./icons/missing.png", &opts),
+            Err(Error::InvalidKeyIndex)
+        ));
+    }
+
+    #[test]
+    fn heartbeat_disconnects() {
+        let (mut deck, mock) = mock_deck(Kind::Xl);
+        mock.set_feature_report(&[0x05, 0, 0, 0, 0, 0, b'1', b'.', b'0']);
+
+        // Reads without a heartbeat are unchanged
+        assert!(matches!(
+            deck.read_buttons(Some(Duration::ZERO)),
+            Err(Error::NoData)
+        ));
+
+        let interval = Duration::from_millis(10);
+        deck.set_heartbeat_interval(Some(interval));
+
+        // Heartbeats succeed while the device responds
+        assert!(matches!(
+            deck.read_buttons(Some(interval * 3)),
+            Err(Error::NoData)
+        ));
+        assert!(deck.is_connected());
+
+        // Input is still returned
+        let mut report = vec![0u8; Kind::Xl.input_report_size()];
+        report[0] = 0x01;
+        report[4] = 1;
+        mock.push_input(&report);
+        assert!(deck.read_buttons(None).unwrap().is_pressed(0));
+
+        // Blocking reads wake to detect the failed heartbeat
+        let (mut deck, _mock) = mock_deck(Kind::Xl);
+        deck.set_heartbeat_interval(Some(interval));
+        assert!(matches!(deck.read_buttons(None), Err(Error::Disconnected)));
+        assert!(!deck.is_connected());
+        assert!(matches!(
+            deck.read_buttons(Some(Duration::ZERO)),
+            Err(Error::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn brightness_reports() {
+        let (mut deck, mock) = mock_deck(Kind::Xl);
+        deck.set_brightness(150).unwrap();
+        let reports = mock.sent_feature_reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0][..3], [0x03, 0x08, 100]);
+
+        let (mut deck, mock) = mock_deck(Kind::Mini);
+        deck.set_brightness(42).unwrap();
+        let reports = mock.sent_feature_reports();
+        assert_eq!(reports[0][..6], [0x05, 0x55, 0xaa, 0xd1, 0x01, 42]);
+    }
+
+    #[test]
+    fn colour_upload_bmp() {
+        let kind = Kind::Mini;
+        let (mut deck, mock) = mock_deck(kind);
+        deck.set_button_rgb(2, &Colour { r: 1, g: 2, b: 3 })
+            .unwrap();
+
+        let packets = mock.written_packets();
+        let hdrlen = kind.image_report_header_len();
+        let payload: Vec<u8> = packets.iter().flat_map(|p| p[hdrlen..].to_vec()).collect();
+
+        // First packet carries the BMP header, followed by BGR pixel data
+        assert_eq!(packets[0][..6], [0x02, 0x01, 0x00, 0x00, 0x00, 2]);
+        assert_eq!(payload[..kind.image_base().len()], *kind.image_base());
+        let pixels = &payload[kind.image_base().len()..];
+        assert_eq!(pixels[..6], [3, 2, 1, 3, 2, 1]);
+
+        // Last packet is flagged
+        let last = packets.last().unwrap();
+        assert_eq!(last[4], 1);
+        assert!(packets[..packets.len() - 1].iter().all(|p| p[4] == 0));
+    }
+
+    #[test]
+    fn sprite_sheet_upload() {
+        let kind = Kind::Mini;
+        let (mut deck, mock) = mock_deck(kind);
+
+        // 2x2 sheet of solid colours
+        let colours = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+        let sheet =
+            ImageBuffer::from_fn(20, 20, |x, y| Rgb(colours[(y / 10 * 2 + x / 10) as usize]));
+        let sheet = DynamicImage::ImageRgb8(sheet);
+
+        deck.set_buttons_from_sheet(&sheet, 2, 2, &[(2, 0), (1, 5)])
+            .unwrap();
+
+        // Split uploads by key, checking the centre pixel of each (BGR)
+        let hdrlen = kind.image_report_header_len();
+        for (key, bgr) in [(0u8, [255, 0, 0]), (5, [0, 255, 0])] {
+            let payload: Vec<u8> = mock
+                .written_packets()
+                .iter()
+                .filter(|p| p[5] == key)
+                .flat_map(|p| p[hdrlen..].to_vec())
+                .collect();
+            let centre = kind.image_base().len() + (40 * 80 + 40) * 3;
+            assert_eq!(payload[centre..centre + 3], bgr, "key {}", key);
+        }
+
+        // Invalid indices are rejected before writing
+        mock.clear();
+        assert!(matches!(
+            deck.set_buttons_from_sheet(&sheet, 2, 2, &[(0, 0), (4, 1)]),
+            Err(Error::InvalidCellIndex)
+        ));
+        assert!(matches!(
+            deck.set_buttons_from_sheet(&sheet, 2, 2, &[(0, 0), (1, 6)]),
+            Err(Error::InvalidKeyIndex)
+        ));
+
+        // As are empty, overflowing and sub-pixel grids
+        for (cols, rows) in [(0, 2), (2, 0), (u32::MAX, 2), (21, 1), (1, 21)] {
+            assert!(matches!(
+                deck.set_buttons_from_sheet(&sheet, cols, rows, &[(0, 0)]),
+                Err(Error::InvalidSheetGrid { .. })
+            ));
+        }
+        assert!(mock.written_packets().is_empty());
+    }
+
+    #[test]
+    fn raw_upload_length() {
+        for kind in [Kind::Original, Kind::Mini, Kind::Xl] {
+            let (mut deck, mock) = mock_deck(kind);
+            let expected = kind.key_image_bytes_len();
+
+            deck.set_button_raw(0, &vec![0u8; expected]).unwrap();
+            assert!(!mock.written_packets().is_empty());
+
+            mock.clear();
+            for got in [0, expected - 1, expected + 3] {
+                match deck.set_button_raw(0, &vec![0u8; got]) {
+                    Err(Error::InvalidImageSize {
+                        got: g,
+                        expected: e,
+                    }) => {
+                        assert_eq!((g, e), (got, expected))
+                    }
+                    r => panic!("unexpected result for {} bytes: {:?}", got, r.err()),
+                }
+            }
+            assert!(mock.written_packets().is_empty());
+        }
+    }
+
+    /// 72x72 test image with a red block in the top left corner
+    fn top_left_marker() -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(72, 72, |x, y| {
+            if x < 24 && y < 24 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        }))
+    }
+
+    #[test]
+    fn original_v1_orientation() {
+        let kind = Kind::Original;
+        let (mut deck, mock) = mock_deck(kind);
+        deck.set_button_image(0, top_left_marker()).unwrap();
+
+        // Two fixed size packets, the first starting with the BMP header
+        let packets = mock.written_packets();
+        assert_eq!(packets.len(), 2);
+        let hdrlen = kind.image_report_header_len();
+        let start = hdrlen + kind.image_base().len();
+        let mut data = packets[0][start..start + 7749].to_vec();
+        data.extend_from_slice(&packets[1][hdrlen..hdrlen + 7803]);
+
+        // Mirrored horizontally, so the marker is on the right, in BGR order
+        let pixel = |x: usize, y: usize| &data[(y * 72 + x) * 3..(y * 72 + x) * 3 + 3];
+        assert_eq!(pixel(71, 0), [0, 0, 255]);
+        assert_eq!(pixel(0, 0), [0, 0, 0]);
+        assert_eq!(pixel(71, 71), [0, 0, 0]);
+    }
+
+    #[test]
+    fn original_v2_orientation() {
+        let kind = Kind::OriginalV2;
+        let (mut deck, mock) = mock_deck(kind);
+        deck.set_button_image(0, top_left_marker()).unwrap();
+
+        let jpeg: Vec<u8> = mock
+            .written_packets()
+            .iter()
+            .flat_map(|p| {
+                let len = u16::from_le_bytes([p[4], p[5]]) as usize;
+                p[8..8 + len].to_vec()
+            })
+            .collect();
+        let image = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+
+        // Rotated 180 degrees, so the marker is in the bottom right
+        let red = |x, y| {
+            let p = image.get_pixel(x, y);
+            p[0] > 200 && p[1] < 50 && p[2] < 50
+        };
+        assert!(red(66, 66));
+        assert!(!red(5, 5));
+        assert!(!red(66, 5));
+        assert!(!red(5, 66));
+    }
+
+    #[test]
+    fn colour_upload_jpeg() {
+        let kind = Kind::Xl;
+        let (mut deck, mock) = mock_deck(kind);
+        deck.set_button_rgb(31, &Colour::RED).unwrap();
+
+        let packets = mock.written_packets();
+        let mut jpeg = Vec::new();
+        for (i, p) in packets.iter().enumerate() {
+            assert_eq!(p.len(), kind.image_report_len());
+            assert_eq!(p[..3], [0x02, 0x07, 31]);
+            assert_eq!(p[3], (i == packets.len() - 1) as u8);
+            assert_eq!(u16::from_le_bytes([p[6], p[7]]), i as u16);
+
+            let len = u16::from_le_bytes([p[4], p[5]]) as usize;
+            jpeg.extend_from_slice(&p[8..8 + len]);
+        }
+
+        let image = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (96, 96));
+        let p = image.get_pixel(48, 48);
+        assert!(p[0] > 240 && p[1] < 16 && p[2] < 16, "{:?}", p);
+    }
+
+    #[test]
+    fn decode_buttons_left_to_right() {
+        let kind = Kind::Xl;
+        let mut report = vec![0u8; kind.input_report_size() - 1];
+        report[kind.key_data_offset()] = 1;
+        report[kind.key_data_offset() + 31] = 1;
+
+        let buttons = decode_buttons(kind, kind.key_data_offset(), &report).unwrap();
+        assert_eq!(buttons.len(), 32);
+        assert_eq!(buttons[0], 1);
+        assert_eq!(buttons[31], 1);
+        assert_eq!(buttons.iter().filter(|b| **b != 0).count(), 2);
+    }
+
+    #[test]
+    fn xl_v2() {
+        // The XL V2 is handled identically to the XL
+        let kind = Kind::from_pid(pids::XL_V2).unwrap();
+        assert_eq!(kind, Kind::Xl);
+        assert_eq!(kind.image_mode(), ImageMode::Jpeg);
+        assert_eq!(kind.image_rotation(), Rotation::Rot0);
+        assert_eq!(kind.image_mirror(), Mirroring::Both);
+
+        let (mut deck, mock) = mock_deck(kind);
+        let mut report = vec![0u8; kind.input_report_size()];
+        report[0] = 0x01;
+        report[1 + kind.key_data_offset() + 31] = 1;
+        mock.push_input(&report);
+        assert_eq!(
+            deck.read_buttons(None)
+                .unwrap()
+                .pressed_indices()
+                .collect::<Vec<_>>(),
+            vec![31]
+        );
+    }
+
+    #[test]
+    fn physical_key_indices() {
+        let (mut deck, _mock) = mock_deck(Kind::Original);
+        assert_eq!(deck.physical_key_index(0).unwrap(), 4);
+        assert_eq!(deck.physical_key_index(5).unwrap(), 9);
+        assert!(deck.physical_key_index(15).is_err());
+
+        deck.set_orientation(Rotation::Rot180).unwrap();
+        assert_eq!(deck.physical_key_index(0).unwrap(), 10);
+
+        let (mut deck, _mock) = mock_deck(Kind::Xl);
+        assert_eq!(deck.physical_key_index(3).unwrap(), 3);
+        deck.set_orientation(Rotation::Rot180).unwrap();
+        assert_eq!(deck.physical_key_index(0).unwrap(), 31);
+    }
+
+    #[test]
+    fn decode_buttons_right_to_left() {
+        let kind = Kind::Original;
+        let mut report = vec![0u8; kind.input_report_size() - 1];
+        // First key in the report is the top right key
+        report[0] = 1;
+
+        let buttons = decode_buttons(kind, kind.key_data_offset(), &report).unwrap();
+        assert_eq!(buttons.len(), 15);
+        assert_eq!(buttons[4], 1);
+        assert_eq!(buttons.iter().filter(|b| **b != 0).count(), 1);
+    }
+
+    #[test]
+    fn decode_buttons_random_reports() {
+        // Simple xorshift so the test is deterministic without extra dependencies
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for kind in (0..=u16::MAX).filter_map(Kind::from_pid) {
+            for _ in 0..1000 {
+                let len = next() as usize % (kind.input_report_size() * 2);
+                let report: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+
+                match decode_buttons(kind, kind.key_data_offset(), &report) {
+                    Ok(buttons) => assert_eq!(buttons.len(), kind.buttons() as usize),
+                    Err(e) => assert!(matches!(e, Error::InvalidReport)),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "hidapi")]
+    #[test]
+    fn open_errors() {
+        let e = |message: &str| {
+            open_error(HidError::HidApiError {
+                message: message.to_string(),
+            })
+        };
+
+        assert!(matches!(
+            e("(iokit/IOHIDDevice.c:1223) kIOReturnExclusiveAccess"),
+            Error::Busy
+        ));
+        assert!(matches!(e("Device or resource busy"), Error::Busy));
+        assert!(matches!(
+            e("Failed to open a device with path '/dev/hidraw3': Permission denied"),
+            Error::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            e("CreateFile: (0x00000005) Access is denied."),
+            Error::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            e("Failed to open a device with path '/dev/hidraw3': No such file or directory"),
+            Error::OpenFailed(_)
+        ));
+        assert!(matches!(
+            open_error(HidError::InitializationError),
+            Error::Hid(_)
+        ));
+    }
+
+    #[cfg(feature = "hidapi")]
+    #[test]
+    fn bootloader_products() {
+        assert!(is_bootloader(Some("Stream Deck Bootloader")));
+        assert!(is_bootloader(Some("Elgato DFU")));
+        assert!(!is_bootloader(Some("Stream Deck XL")));
+        assert!(!is_bootloader(None));
+    }
+
+    #[cfg(feature = "hidapi")]
+    #[test]
+    fn list_kind_filter() {
+        let device = |pid: u16, kind: Kind| DeviceInfo {
+            vid: ELGATO_VID,
+            pid,
+            serial: None,
+            path: format!("/dev/hidraw{}", pid),
+            kind,
+        };
+        let devices = vec![
+            device(pids::MINI, Kind::Mini),
+            device(pids::XL, Kind::Xl),
+            device(pids::MK2, Kind::Mk2),
+            device(pids::XL_V2, Kind::Xl),
+        ];
+
+        let xl = filter_kind(devices.clone(), Kind::Xl);
+        assert_eq!(
+            xl.iter().map(|d| d.pid).collect::<Vec<_>>(),
+            vec![pids::XL, pids::XL_V2]
+        );
+        assert_eq!(
+            filter_kind(devices.clone(), Kind::Mk2),
+            vec![devices[2].clone()]
+        );
+        assert!(filter_kind(devices, Kind::Original).is_empty());
+    }
+
+    #[cfg(feature = "hidapi")]
+    #[test]
+    fn serial_prefix_matching() {
+        let serials = [
+            "AL12K1A00001",
+            "AL12K1A00002",
+            "BL34K1A00001",
+            "AL12K1A00001",
+        ];
+        let m = |prefix: &str| match_serial(serials.iter().copied(), prefix);
+
+        assert_eq!(m("BL").unwrap(), Some("BL34K1A00001".to_string()));
+        assert_eq!(m("AL12K1A00002").unwrap(), Some("AL12K1A00002".to_string()));
+        assert_eq!(m("CL").unwrap(), None);
+        assert!(matches!(m("AL12"), Err(Error::AmbiguousSerial(v)) if v.len() == 2));
+
+        // Exact matches are preferred over longer serials sharing the prefix
+        let serials = ["AL12", "AL123"];
+        assert_eq!(
+            match_serial(serials.iter().copied(), "AL12").unwrap(),
+            Some("AL12".to_string())
+        );
+    }
+
+    #[test]
+    fn translate_key_index_bounds() {
+        for kind in (0..=u16::MAX).filter_map(Kind::from_pid) {
+            assert!(kind.translate_key_index(kind.keys() - 1).is_ok());
+            assert!(kind.translate_key_index(kind.keys()).is_err());
+            assert!(kind.translate_key_index(u8::MAX).is_err());
+        }
+    }
+}
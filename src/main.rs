@@ -9,7 +9,17 @@ use structopt::StructOpt;
 extern crate humantime;
 use humantime::Duration;
 
-use streamdeck::{StreamDeck, Filter, Colour, ImageOptions, Error};
+#[cfg(feature = "text")]
+extern crate ab_glyph;
+#[cfg(feature = "text")]
+use ab_glyph::{FontRef, PxScale};
+
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use streamdeck::{StreamDeck, Filter, Colour, ImageOptions, Error, InputManager};
+#[cfg(feature = "text")]
+use streamdeck::{TextOptions, TextPosition};
 
 #[derive(StructOpt)]
 #[structopt(name = "streamdeck-cli", about = "A CLI for the Elgato StreamDeck")]
@@ -28,6 +38,8 @@ struct Options {
 
 #[derive(StructOpt)]
 pub enum Commands {
+    /// List attached devices
+    List,
     /// Reset the attached device
     Reset,
     /// Fetch the device firmware version
@@ -47,6 +59,25 @@ pub enum Commands {
         /// Read continuously
         continuous: bool,
     },
+    /// Fetch input events
+    GetInput {
+        #[structopt(long)]
+        /// Timeout for input reading
+        timeout: Option<Duration>,
+
+        #[structopt(long)]
+        /// Read continuously
+        continuous: bool,
+
+        #[structopt(long)]
+        /// Emit an idle event after this long without input
+        idle_timeout: Option<Duration>,
+
+        #[structopt(long)]
+        /// Minimum time between reads while no input is available, reducing CPU use
+        /// with short timeouts at the cost of input latency
+        poll_interval: Option<Duration>,
+    },
     /// Set button colours
     SetColour {
         /// Index of button to be set
@@ -65,7 +96,54 @@ pub enum Commands {
 
         #[structopt(flatten)]
         opts: ImageOptions,
-    }
+    },
+    /// Set button text
+    #[cfg(feature = "text")]
+    SetText {
+        /// Index of button to be set
+        key: u8,
+
+        /// Text to be drawn, use \n for line breaks
+        text: String,
+
+        #[structopt(long)]
+        /// TrueType / OpenType font file
+        font: String,
+
+        #[structopt(long, default_value = "15")]
+        /// Font size in pixels
+        size: f32,
+
+        #[structopt(long, default_value = "ffffff")]
+        /// Text colour in hex (RRGGBB)
+        fg: Colour,
+
+        #[structopt(long, default_value = "000000")]
+        /// Background colour in hex (RRGGBB)
+        bg: Colour,
+
+        #[structopt(long, default_value = "0")]
+        /// Text X position
+        x: i32,
+
+        #[structopt(long, default_value = "0")]
+        /// Text Y position
+        y: i32,
+    },
+    /// Run a device self-test, cycling keys and brightness and reading input
+    Diagnose {
+        #[structopt(long)]
+        /// TrueType / OpenType font file for numbered key images (skipped if not provided)
+        font: Option<String>,
+
+        #[structopt(long, default_value = "3")]
+        /// Number of input reports to read
+        reports: usize,
+
+        #[structopt(long, default_value = "1s")]
+        /// Timeout for each input report
+        timeout: Duration,
+    },
 }
 
 fn main() {
@@ -78,11 +156,25 @@ fn main() {
 
     TermLogger::init(opts.level, config.build(), TerminalMode::Mixed, ColorChoice::Auto).unwrap();
 
+    // Listing does not require a connection
+    if let Commands::List = opts.cmd {
+        match StreamDeck::list() {
+            Ok(devices) => for d in devices {
+                info!("{} (vid: {:04x} pid: {:04x} serial: {}) at {}",
+                        d.kind.name(), d.vid, d.pid, d.serial.as_deref().unwrap_or("unknown"), d.path);
+                info!("  {}", d.kind.capabilities());
+            },
+            Err(e) => error!("Error listing devices: {:?}", e),
+        }
+        return
+    }
+
     // Connect to device
-    let mut deck = match StreamDeck::connect(opts.filter.vid, opts.filter.pid, opts.filter.serial) {
+    let serial_match = opts.filter.serial_match();
+    let mut deck = match StreamDeck::connect_matching(opts.filter.vid, opts.filter.pid, opts.filter.serial, serial_match) {
         Ok(d) => d,
         Err(e) => {
-            error!("Error connecting to streamdeck: {:?}", e);
+            error!("Error connecting to streamdeck: {}", e);
             return
         }
     };
@@ -99,6 +191,7 @@ fn main() {
 
 fn do_command(deck: &mut StreamDeck, cmd: Commands) -> Result<(), Error> {
     match cmd {
+        Commands::List => unreachable!(),
         Commands::Reset => {
             deck.reset()?;
         },
@@ -112,13 +205,29 @@ fn do_command(deck: &mut StreamDeck, cmd: Commands) -> Result<(), Error> {
         Commands::GetButtons{timeout, continuous} => {
             loop {
                 let buttons = deck.read_buttons(timeout.map(|t| *t ))?;
-                info!("buttons: {:?}", buttons);
+                info!("buttons: {:?} pressed: {:?}", buttons.as_raw(), buttons.pressed_indices().collect::<Vec<_>>());
 
                 if !continuous {
                     break
                 }
             }
         },
+        Commands::GetInput{timeout, continuous, idle_timeout, poll_interval} => {
+            let mut manager = InputManager::new(deck);
+            manager.set_idle_timeout(idle_timeout.map(|t| *t ));
+            manager.set_poll_interval(poll_interval.map(|t| *t ));
+
+            manager.run(timeout.map(|t| *t ), |events| {
+                for event in events {
+                    info!("event: {:?}", event);
+                }
+
+                match continuous {
+                    true => ControlFlow::Continue(()),
+                    false => ControlFlow::Break(()),
+                }
+            })?;
+        },
         Commands::SetColour{key, colour} => {
             info!("Setting key {} colour to: ({:?})", key, colour);
             deck.set_button_rgb(key, &colour)?;
@@ -126,8 +235,150 @@ fn do_command(deck: &mut StreamDeck, cmd: Commands) -> Result<(), Error> {
         Commands::SetImage{key, file, opts} => {
             info!("Setting key {} to image: {}", key, file);
             deck.set_button_file(key, &file, &opts)?;
+        },
+        #[cfg(feature = "text")]
+        Commands::SetText{key, text, font, size, fg, bg, x, y} => {
+            info!("Setting key {} to text: {}", key, text);
+
+            let data = std::fs::read(&font)?;
+            let font = FontRef::try_from_slice(&data)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+            let opts = TextOptions::new(fg, bg, PxScale::from(size), 1.1);
+            let text = text.replace("\\n", "\n");
+            deck.set_button_text(key, &font, &TextPosition::Absolute{x, y}, &text, &opts)?;
+        },
+        Commands::Diagnose{font, reports, timeout} => {
+            diagnose(deck, font, reports, *timeout)?;
+        },
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "text")]
+fn upload_numbered_keys(deck: &mut StreamDeck, font: &str) -> Result<(), Error> {
+    info!("Uploading numbered key images");
+
+    let data = std::fs::read(font)?;
+    let font = FontRef::try_from_slice(&data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    let (w, h) = deck.kind().image_size();
+    let size = h as f32 / 2.0;
+    let opts = TextOptions::new(Colour::WHITE, Colour::BLACK, PxScale::from(size), 1.1);
+    let pos = TextPosition::Absolute{ x: (w as f32 / 2.0 - size / 2.0) as i32, y: (h as f32 / 2.0 - size / 2.0) as i32 };
+
+    for key in 0..deck.keys() {
+        deck.set_button_text(key, &font, &pos, &key.to_string(), &opts)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "text"))]
+fn upload_numbered_keys(_deck: &mut StreamDeck, _font: &str) -> Result<(), Error> {
+    info!("Skipping numbered key images, built without text support");
+    Ok(())
+}
+
+/// Set on Ctrl-C once [catch_interrupt] is called, so commands can stop and tidy up the device
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+fn catch_interrupt() {
+    extern "C" fn on_interrupt(_signal: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    // Safety: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as *const () as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_interrupt() {}
+
+fn diagnose(deck: &mut StreamDeck, font: Option<String>, reports: usize, timeout: std::time::Duration) -> Result<(), Error> {
+    // Stop on Ctrl-C (checked between steps), still resetting the device
+    catch_interrupt();
+
+    let res = diagnose_stages(deck, font, reports, timeout);
+    match INTERRUPTED.load(Ordering::SeqCst) {
+        true => info!("Self-test interrupted, resetting device"),
+        false => info!("Self-test complete, resetting device"),
+    }
+    let reset = deck.reset();
+
+    res.and(reset)
+}
+
+fn diagnose_stages(deck: &mut StreamDeck, font: Option<String>, reports: usize, timeout: std::time::Duration) -> Result<(), Error> {
+    let kind = deck.kind();
+    let interrupted = || INTERRUPTED.load(Ordering::SeqCst);
+
+    info!("Device: {} ({:?})", kind.name(), kind);
+    info!("Serial: {}", deck.serial()?);
+    info!("Firmware: {}", deck.firmware_version()?);
+    info!("Capabilities: {}", kind.capabilities());
+
+    match kind.has_display() {
+        true => diagnose_display(deck, font)?,
+        false => info!("Skipping display tests, device has no display"),
+    }
+
+    info!("Reading {} input reports, press some keys", reports);
+    for i in 0..reports {
+        if interrupted() {
+            return Ok(());
+        }
+
+        match deck.read_buttons(Some(timeout)) {
+            Ok(buttons) => info!("Report {}: {:?} pressed: {:?}", i, buttons.as_raw(), buttons.pressed_indices().collect::<Vec<_>>()),
+            Err(Error::NoData) => info!("Report {}: no input within {:?}", i, timeout),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn diagnose_display(deck: &mut StreamDeck, font: Option<String>) -> Result<(), Error> {
+    let kind = deck.kind();
+    let step = std::time::Duration::from_millis(100);
+    let interrupted = || INTERRUPTED.load(Ordering::SeqCst);
+
+    info!("Cycling key colours");
+    for colour in &[Colour::RED, Colour::GREEN, Colour::BLUE] {
+        if interrupted() {
+            return Ok(());
+        }
+        for key in 0..kind.keys() {
+            deck.set_button_rgb(key, colour)?;
+        }
+        std::thread::sleep(step * 5);
+    }
+
+    match font {
+        Some(_) if interrupted() => return Ok(()),
+        Some(font) => {
+            upload_numbered_keys(deck, &font)?;
+            std::thread::sleep(step * 10);
+        },
+        None => info!("Skipping numbered key images, no --font provided"),
+    }
+
+    info!("Sweeping brightness");
+    for b in (0..=100).step_by(10).chain((0..100).step_by(10).rev()) {
+        if interrupted() {
+            return Ok(());
         }
+        debug!("Brightness: {}", b);
+        deck.set_brightness(b)?;
+        std::thread::sleep(step);
     }
+    deck.set_brightness(100)?;
 
     Ok(())
 }
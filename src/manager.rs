@@ -0,0 +1,220 @@
+//! Management of multiple devices, with input from all devices on a single channel
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::input::listen;
+use crate::{Error, InputEvent, InputState, SharedStreamDeck, StreamDeck};
+
+/// Input event (or device error) from a managed device, see [DeckManager::events]
+#[derive(Debug)]
+pub struct DeckEvent {
+    /// Identifier of the device the event is from
+    pub id: String,
+    /// Input event, or the error that stopped reading from the device
+    pub event: Result<InputEvent, Error>,
+}
+
+/// Managed device and its reader thread
+struct Managed {
+    id: String,
+    deck: SharedStreamDeck,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Manager for multiple devices, addressed by identifier (usually the serial) or index
+///
+/// Input from each device is read on a background thread (as with
+/// [InputListener](crate::InputListener)) and delivered tagged with the device
+/// identifier on a single channel. Devices remain available for writes through
+/// their [SharedStreamDeck] handles.
+pub struct DeckManager {
+    decks: Vec<Managed>,
+    timeout: Duration,
+    tx: Sender<DeckEvent>,
+    events: Receiver<DeckEvent>,
+    stop: Arc<AtomicBool>,
+}
+
+impl DeckManager {
+    /// Create an empty manager, with each read waiting up to `timeout`
+    pub fn new(timeout: Duration) -> Self {
+        let (tx, events) = channel();
+
+        Self {
+            decks: Vec::new(),
+            timeout,
+            tx,
+            events,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Open all attached devices, identified by serial (or path where the
+    /// serial is not available)
+    ///
+    /// Devices are opened in enumeration order, see [StreamDeck::list].
+    #[cfg(feature = "hidapi")]
+    pub fn open_all(timeout: Duration) -> Result<Self, Error> {
+        let mut manager = Self::new(timeout);
+
+        for info in StreamDeck::list()? {
+            let deck = StreamDeck::connect_matching(
+                info.vid,
+                info.pid,
+                info.serial.clone(),
+                crate::SerialMatch::Exact,
+            )?;
+            manager.add(info.serial.unwrap_or(info.path), deck)?;
+        }
+
+        Ok(manager)
+    }
+
+    /// Add a device with the provided identifier, starting to read input,
+    /// returning the device index
+    ///
+    /// Returns [Error::DuplicateId] if the identifier is already in use.
+    pub fn add(&mut self, id: impl Into<String>, deck: StreamDeck) -> Result<usize, Error> {
+        let id = id.into();
+        if self.decks.iter().any(|d| d.id == id) {
+            return Err(Error::DuplicateId(id));
+        }
+
+        let state = InputState::new(deck.kind().buttons(), Instant::now());
+        let deck = SharedStreamDeck::new(deck);
+
+        let (d, tx, stop, timeout, i) = (
+            deck.clone(),
+            self.tx.clone(),
+            self.stop.clone(),
+            self.timeout,
+            id.clone(),
+        );
+        let handle = std::thread::spawn(move || {
+            let send = |event| {
+                let _ = tx.send(DeckEvent {
+                    id: i.clone(),
+                    event,
+                });
+            };
+            if let Err(e) = listen(d, state, timeout, &stop, |e| send(Ok(e))) {
+                send(Err(e));
+            }
+        });
+
+        self.decks.push(Managed {
+            id,
+            deck,
+            handle: Some(handle),
+        });
+
+        Ok(self.decks.len() - 1)
+    }
+
+    /// Fetch the number of managed devices
+    pub fn len(&self) -> usize {
+        self.decks.len()
+    }
+
+    /// Check whether there are no managed devices
+    pub fn is_empty(&self) -> bool {
+        self.decks.is_empty()
+    }
+
+    /// Iterate over the device identifiers, in index order
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.decks.iter().map(|d| d.id.as_str())
+    }
+
+    /// Fetch a device by index
+    pub fn get(&self, index: usize) -> Option<&SharedStreamDeck> {
+        self.decks.get(index).map(|d| &d.deck)
+    }
+
+    /// Fetch a device by identifier
+    pub fn by_id(&self, id: &str) -> Option<&SharedStreamDeck> {
+        self.decks.iter().find(|d| d.id == id).map(|d| &d.deck)
+    }
+
+    /// Fetch the receiver for input events from all devices
+    pub fn events(&self) -> &Receiver<DeckEvent> {
+        &self.events
+    }
+}
+
+impl Drop for DeckManager {
+    /// Reader threads are stopped before the devices are closed
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        for d in self.decks.iter_mut() {
+            if let Some(h) = d.handle.take() {
+                let _ = h.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ButtonAction, Colour, Kind, MockTransport};
+
+    #[test]
+    fn multiple_devices() {
+        let (left, right) = (MockTransport::new(), MockTransport::new());
+        let mut manager = DeckManager::new(Duration::from_millis(1));
+
+        let left_deck = StreamDeck::with_transport(left.clone(), Kind::Mini);
+        assert_eq!(manager.add("LEFT", left_deck).unwrap(), 0);
+        let right_deck = StreamDeck::with_transport(right.clone(), Kind::Xl);
+        assert_eq!(manager.add("RIGHT", right_deck).unwrap(), 1);
+
+        let duplicate = StreamDeck::with_transport(MockTransport::new(), Kind::Mini);
+        assert!(matches!(
+            manager.add("LEFT", duplicate),
+            Err(Error::DuplicateId(id)) if id == "LEFT"
+        ));
+        assert_eq!(manager.ids().collect::<Vec<_>>(), vec!["LEFT", "RIGHT"]);
+
+        // Devices are addressed by index or identifier
+        assert_eq!(manager.get(1).unwrap().kind(), Kind::Xl);
+        manager
+            .by_id("LEFT")
+            .unwrap()
+            .set_button_rgb(2, Colour::RED)
+            .unwrap();
+        assert!(!left.written_packets().is_empty());
+        assert!(right.written_packets().is_empty());
+
+        // Input is tagged with the device
+        let mut report = vec![0u8; Kind::Xl.input_report_size()];
+        report[0] = 0x01;
+        report[4 + 7] = 1;
+        right.push_input(&report);
+
+        let e = manager
+            .events()
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(e.id, "RIGHT");
+        assert_eq!(
+            e.event.unwrap(),
+            InputEvent::Button {
+                index: 7,
+                action: ButtonAction::Pressed
+            }
+        );
+
+        left.push_input(&[0x01, 1, 0, 0, 0, 0, 0]);
+        let e = manager
+            .events()
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(e.id, "LEFT");
+    }
+}
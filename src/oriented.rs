@@ -0,0 +1,122 @@
+//! Orientation specific key images, for devices that may be remounted
+
+use image::DynamicImage;
+
+use crate::{Error, Rotation, StreamDeck};
+
+/// Key image with variants for specific mounting orientations, see [StreamDeck::set_button_oriented]
+///
+/// Variants are authored as seen by the user with the device mounted in that orientation,
+/// so are rotated for the device in the same way as any other image.
+#[derive(Clone)]
+pub struct OrientedImage {
+    default: DynamicImage,
+    variants: Vec<(Rotation, DynamicImage)>,
+}
+
+impl OrientedImage {
+    /// Create an oriented image, using the provided image for orientations without a variant
+    pub fn new(default: DynamicImage) -> Self {
+        Self {
+            default,
+            variants: Vec::new(),
+        }
+    }
+
+    /// Set the image variant for an orientation, replacing any existing variant
+    pub fn with_orientation(mut self, orientation: Rotation, image: DynamicImage) -> Self {
+        self.variants.retain(|(r, _)| *r != orientation);
+        self.variants.push((orientation, image));
+        self
+    }
+
+    /// Fetch the image for the provided orientation
+    pub fn for_orientation(&self, orientation: &Rotation) -> &DynamicImage {
+        self.variants
+            .iter()
+            .find(|(r, _)| r == orientation)
+            .map(|(_, i)| i)
+            .unwrap_or(&self.default)
+    }
+}
+
+impl StreamDeck {
+    /// Set a button to the variant of the image for the current orientation
+    ///
+    /// The image is kept, so the matching variant is uploaded again when the
+    /// orientation is changed with [StreamDeck::set_orientation], until other
+    /// content is written to the key.
+    pub fn set_button_oriented(&mut self, key: u8, image: OrientedImage) -> Result<(), Error> {
+        self.set_button_image(key, image.for_orientation(&self.orientation).clone())?;
+        self.oriented_images[key as usize] = Some(image);
+
+        Ok(())
+    }
+
+    /// Upload the variants for the current orientation of all oriented images, as a single frame
+    pub(crate) fn redraw_oriented(&mut self) -> Result<(), Error> {
+        let images = std::mem::take(&mut self.oriented_images);
+
+        let r = self.frame(|deck| {
+            for (key, image) in images.iter().enumerate() {
+                if let Some(image) = image {
+                    let variant = image.for_orientation(&deck.orientation).clone();
+                    deck.set_button_image(key as u8, variant)?;
+                }
+            }
+            Ok(())
+        });
+
+        self.oriented_images = images;
+        r
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Kind, MockTransport};
+    use image::{Rgb, RgbImage};
+
+    fn solid(kind: Kind, c: [u8; 3]) -> DynamicImage {
+        let (w, h) = kind.image_size();
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(w as u32, h as u32, Rgb(c)))
+    }
+
+    #[test]
+    fn oriented_variants() {
+        let kind = Kind::Xl;
+        let (up, down) = (solid(kind, [255, 0, 0]), solid(kind, [0, 0, 255]));
+        let image = OrientedImage::new(up.clone()).with_orientation(Rotation::Rot180, down.clone());
+
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), kind);
+
+        // Packets expected for each variant
+        let expected = |rotation: Rotation, image: &DynamicImage| {
+            let mock = MockTransport::new();
+            let mut deck = StreamDeck::with_transport(mock.clone(), kind);
+            deck.set_orientation(rotation).unwrap();
+            deck.set_button_image(3, image.clone()).unwrap();
+            mock.written_packets()
+        };
+
+        deck.set_button_oriented(3, image).unwrap();
+        assert_eq!(mock.written_packets(), expected(Rotation::Rot0, &up));
+
+        // Changing the orientation uploads the matching variant
+        mock.clear();
+        deck.set_orientation(Rotation::Rot180).unwrap();
+        assert_eq!(mock.written_packets(), expected(Rotation::Rot180, &down));
+
+        mock.clear();
+        deck.set_orientation(Rotation::Rot0).unwrap();
+        assert_eq!(mock.written_packets(), expected(Rotation::Rot0, &up));
+
+        // Other content replaces the oriented image
+        deck.set_button_rgb(3, crate::Colour::GREEN).unwrap();
+        mock.clear();
+        deck.set_orientation(Rotation::Rot180).unwrap();
+        assert!(mock.written_packets().is_empty());
+    }
+}
@@ -0,0 +1,495 @@
+//! Automatic reconnection, for devices that may be reset or briefly unplugged
+
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+
+use crate::{ButtonStates, Colour, Error, InputEvent, InputManager, InputState, StreamDeck};
+#[cfg(feature = "hidapi")]
+use crate::{DeviceClaim, SerialMatch};
+
+/// Retry policy for [ReconnectingStreamDeck]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Attempts to reopen the device after the first fails
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each following retry
+    pub initial_backoff: Duration,
+    /// Maximum delay between retries
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    /// default is 5 retries, backing off from 100ms to at most 5s
+    fn default() -> Self {
+        Self {
+            retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Function opening a new connection to the device
+type Opener = Box<dyn FnMut() -> Result<StreamDeck, Error> + Send>;
+
+/// Device wrapper reopening the device when a call fails with a device error
+///
+/// Where a call fails with [Error::Hid], [Error::Io] or [Error::Disconnected] the device
+/// is reopened, retrying as configured by the [ReconnectPolicy], the brightness and last
+/// image written to each key are restored, and the call is retried once.
+///
+/// Device settings (such as orientation and image options) are kept across reconnects,
+/// as the connection is replaced within the same [StreamDeck].
+pub struct ReconnectingStreamDeck {
+    deck: StreamDeck,
+    open: Opener,
+    policy: ReconnectPolicy,
+}
+
+impl ReconnectingStreamDeck {
+    /// Connect to a device, reopening the same device (matched by serial) on failure
+    ///
+    /// See [StreamDeck::connect].
+    #[cfg(feature = "hidapi")]
+    pub fn connect(
+        vid: u16,
+        pid: u16,
+        serial: Option<String>,
+        policy: ReconnectPolicy,
+    ) -> Result<Self, Error> {
+        let mut deck = StreamDeck::connect(vid, pid, serial.clone())?;
+
+        // Match the connected device exactly, so another device is not opened on reconnect
+        let (serial, mode) = match deck.serial() {
+            Ok(s) => (Some(s), SerialMatch::Exact),
+            Err(_) => (serial, SerialMatch::Prefix),
+        };
+
+        let open = move || StreamDeck::connect_matching(vid, pid, serial.clone(), mode);
+
+        Ok(Self::with_opener(deck, Box::new(open), policy))
+    }
+
+    /// Wrap a connected device, using the provided function to reopen it
+    pub fn with_opener(mut deck: StreamDeck, open: Opener, policy: ReconnectPolicy) -> Self {
+        deck.retained_images = Some(vec![None; deck.kind.keys() as usize]);
+
+        Self { deck, open, policy }
+    }
+
+    /// Fetch the device, for calls that should not reconnect on failure
+    pub fn deck(&mut self) -> &mut StreamDeck {
+        &mut self.deck
+    }
+
+    /// Run the provided closure with the device, reconnecting and retrying once on failure
+    pub fn with<R, F>(&mut self, mut f: F) -> Result<R, Error>
+    where
+        F: FnMut(&mut StreamDeck) -> Result<R, Error>,
+    {
+        match f(&mut self.deck) {
+            Err(e) if is_disconnect(&e) => {
+                warn!("Device error ({}), reconnecting", e);
+                self.reconnect()?;
+                f(&mut self.deck)
+            }
+            r => r,
+        }
+    }
+
+    /// Reopen the device and restore the brightness and key images
+    ///
+    /// Returns the error from the last attempt where the device cannot be reopened.
+    ///
+    /// The device remains claimed (see [Error::AlreadyOpen]) between failed attempts.
+    pub fn reconnect(&mut self) -> Result<(), Error> {
+        let mut backoff = self.policy.initial_backoff;
+        let mut attempt = 0;
+
+        loop {
+            match self.attempt() {
+                Ok(new) => {
+                    self.deck.replace_connection(new)?;
+                    info!("Reconnected after {} attempt(s)", attempt + 1);
+                    return self.deck.restore();
+                }
+                Err(e) if attempt >= self.policy.retries => return Err(e),
+                Err(e) => debug!("Reconnect attempt {} failed: {}", attempt + 1, e),
+            }
+
+            std::thread::sleep(backoff);
+            backoff = (backoff * 2).min(self.policy.max_backoff);
+            attempt += 1;
+        }
+    }
+
+    /// Attempt to reopen the device, releasing the claim on it only while opening
+    fn attempt(&mut self) -> Result<StreamDeck, Error> {
+        // Release the device so the same path can be reopened
+        #[cfg(feature = "hidapi")]
+        let key = self.deck.claim.take().map(|c| c.key().to_string());
+
+        let r = (self.open)();
+
+        #[cfg(feature = "hidapi")]
+        if let (Err(_), Some(key)) = (&r, key) {
+            match DeviceClaim::new(&key) {
+                Ok(c) => self.deck.claim = Some(c),
+                Err(e) => warn!("Failed to reclaim device {}: {}", key, e),
+            }
+        }
+
+        r
+    }
+
+    /// See [StreamDeck::set_brightness]
+    pub fn set_brightness(&mut self, brightness: u8) -> Result<(), Error> {
+        self.with(|d| d.set_brightness(brightness))
+    }
+
+    /// See [StreamDeck::set_button_rgb]
+    pub fn set_button_rgb(&mut self, key: u8, colour: impl Into<Colour>) -> Result<(), Error> {
+        let colour = colour.into();
+        self.with(|d| d.set_button_rgb(key, &colour))
+    }
+
+    /// See [StreamDeck::set_button_image]
+    pub fn set_button_image(&mut self, key: u8, image: DynamicImage) -> Result<(), Error> {
+        self.with(|d| d.set_button_image(key, image.clone()))
+    }
+
+    /// See [StreamDeck::read_buttons]
+    pub fn read_buttons(&mut self, timeout: Option<Duration>) -> Result<ButtonStates, Error> {
+        self.with(|d| d.read_buttons(timeout))
+    }
+
+    /// See [StreamDeck::flush]
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.with(|d| d.flush())
+    }
+
+    /// Repeatedly read input as [InputManager::run], reconnecting on device errors
+    ///
+    /// Input state is kept across reconnects, so keys released while the device was
+    /// disconnected are reported as released by the first read after reconnecting.
+    /// Returns the reconnect error where the device cannot be reopened.
+    pub fn run<F>(&mut self, timeout: Option<Duration>, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(Vec<InputEvent>) -> ControlFlow<()>,
+    {
+        let mut state = InputState::new(self.deck.kind().buttons(), Instant::now());
+
+        loop {
+            let mut manager = InputManager::with_state(&mut self.deck, state);
+            let res = manager.run(timeout, &mut f);
+            state = manager.into_state();
+
+            match res {
+                Err(e) if is_disconnect(&e) => {
+                    warn!("Device error ({}), reconnecting", e);
+                    self.reconnect()?;
+                }
+                r => return r,
+            }
+        }
+    }
+}
+
+/// Check whether an error indicates the device connection has failed
+fn is_disconnect(e: &Error) -> bool {
+    match e {
+        #[cfg(feature = "hidapi")]
+        Error::Hid(_) => true,
+        Error::Io(_) | Error::Disconnected => true,
+        _ => false,
+    }
+}
+
+impl StreamDeck {
+    /// Replace the connection to the device with that of a newly opened device object,
+    /// applying the current blocking mode to the new connection
+    fn replace_connection(&mut self, other: StreamDeck) -> Result<(), Error> {
+        std::mem::swap(&mut *self.device(), &mut *other.device());
+        #[cfg(feature = "hidapi")]
+        {
+            let mut other = other;
+            self.claim = other.claim.take();
+        }
+
+        self.connected = true;
+        self.last_heartbeat = Instant::now();
+
+        self.device().set_blocking_mode(self.blocking)
+    }
+
+    /// Write the brightness and retained key images to a newly opened device
+    ///
+    /// Keys with images buffered in an open frame are left to be written when the frame is
+    /// flushed, and while asleep images are buffered until wake.
+    fn restore(&mut self) -> Result<(), Error> {
+        if let Some(b) = self.brightness {
+            self.write_brightness(if self.sleeping { 0 } else { b })?;
+        }
+
+        let images = self.retained_images.clone().unwrap_or_default();
+        for (key, image) in images.into_iter().enumerate() {
+            let key = key as u8;
+            let buffered = self
+                .frame
+                .as_ref()
+                .is_some_and(|f| f.iter().any(|(k, _)| *k == key));
+
+            match image {
+                Some(_) if buffered => (),
+                Some(image) if self.sleeping => {
+                    self.frame.get_or_insert_with(Vec::new).push((key, image))
+                }
+                Some(image) => self.upload_button_image(key, &image)?,
+                None => (),
+            }
+        }
+
+        match &self.writer {
+            Some(w) => w.wait(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{DeckTransport, Kind, MockTransport};
+
+    /// Transport failing all operations once unplugged
+    struct Unpluggable {
+        inner: MockTransport,
+        unplugged: Arc<AtomicBool>,
+    }
+
+    impl Unpluggable {
+        fn check(&self) -> Result<(), Error> {
+            match self.unplugged.load(Ordering::Relaxed) {
+                true => Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe).into()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl DeckTransport for Unpluggable {
+        fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+            self.check()?;
+            self.inner.write(data)
+        }
+
+        fn read(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> Result<usize, Error> {
+            self.check()?;
+            self.inner.read(buf, timeout)
+        }
+
+        fn send_feature_report(&mut self, data: &[u8]) -> Result<(), Error> {
+            self.check()?;
+            self.inner.send_feature_report(data)
+        }
+
+        fn get_feature_report(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.check()?;
+            self.inner.get_feature_report(buf)
+        }
+
+        fn set_blocking_mode(&mut self, blocking: bool) -> Result<(), Error> {
+            self.inner.set_blocking_mode(blocking)
+        }
+    }
+
+    #[test]
+    fn reconnect_and_restore() {
+        let kind = Kind::Mini;
+        let unplugged = Arc::new(AtomicBool::new(false));
+        let first = StreamDeck::with_transport(
+            Unpluggable {
+                inner: MockTransport::new(),
+                unplugged: unplugged.clone(),
+            },
+            kind,
+        );
+
+        // Fails twice before the device is found again
+        let (mock, attempts) = (MockTransport::new(), Arc::new(AtomicU32::new(0)));
+        let (m, a) = (mock.clone(), attempts.clone());
+        let open = move || match a.fetch_add(1, Ordering::Relaxed) {
+            0 | 1 => Err(Error::OpenFailed("not found".to_string())),
+            _ => Ok(StreamDeck::with_transport(m.clone(), kind)),
+        };
+        let policy = ReconnectPolicy {
+            retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        };
+        let mut deck = ReconnectingStreamDeck::with_opener(first, Box::new(open), policy);
+
+        deck.set_brightness(40).unwrap();
+        deck.set_button_rgb(1, Colour::RED).unwrap();
+        deck.set_button_rgb(4, Colour::GREEN).unwrap();
+
+        // The failed write is retried after restoring the device state
+        unplugged.store(true, Ordering::Relaxed);
+        deck.set_button_rgb(4, Colour::BLUE).unwrap();
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+
+        let expected = MockTransport::new();
+        let mut other = StreamDeck::with_transport(expected.clone(), kind);
+        other.set_brightness(40).unwrap();
+        for (key, colour) in [(1, Colour::RED), (4, Colour::GREEN), (4, Colour::BLUE)] {
+            other.set_button_rgb(key, colour).unwrap();
+        }
+        assert_eq!(mock.written_packets(), expected.written_packets());
+        assert_eq!(mock.sent_feature_reports(), expected.sent_feature_reports());
+
+        // Other errors are returned without reconnecting
+        assert!(matches!(
+            deck.set_button_rgb(6, Colour::RED),
+            Err(Error::InvalidKeyIndex)
+        ));
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn reconnect_blocking_mode() {
+        let mock = MockTransport::new();
+        let m = mock.clone();
+        let open = move || Ok(StreamDeck::with_transport(m.clone(), Kind::Mini));
+
+        let deck = StreamDeck::with_transport(MockTransport::new(), Kind::Mini);
+        let mut deck =
+            ReconnectingStreamDeck::with_opener(deck, Box::new(open), ReconnectPolicy::default());
+        deck.deck().set_blocking(false).unwrap();
+
+        deck.reconnect().unwrap();
+        assert_eq!(mock.blocking_mode(), Some(false));
+    }
+
+    #[test]
+    fn run_reconnecting() {
+        let kind = Kind::Mini;
+        let (first, unplugged) = (MockTransport::new(), Arc::new(AtomicBool::new(false)));
+        let deck = StreamDeck::with_transport(
+            Unpluggable {
+                inner: first.clone(),
+                unplugged: unplugged.clone(),
+            },
+            kind,
+        );
+
+        let mock = MockTransport::new();
+        let m = mock.clone();
+        let open = move || Ok(StreamDeck::with_transport(m.clone(), kind));
+        let mut deck =
+            ReconnectingStreamDeck::with_opener(deck, Box::new(open), ReconnectPolicy::default());
+
+        // The key is released while the device is unplugged
+        first.push_input(&[0x01, 0, 1, 0, 0, 0, 0]);
+        mock.push_input(&[0x01, 0, 0, 0, 0, 0, 0]);
+
+        let mut events = Vec::new();
+        deck.run(None, |batch| {
+            unplugged.store(true, Ordering::Relaxed);
+            events.extend(batch);
+            match events.len() {
+                2 => ControlFlow::Break(()),
+                _ => ControlFlow::Continue(()),
+            }
+        })
+        .unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                InputEvent::Button {
+                    index: 1,
+                    action: crate::ButtonAction::Pressed
+                },
+                InputEvent::Button {
+                    index: 1,
+                    action: crate::ButtonAction::Released
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn reconnect_retries_exhausted() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let a = attempts.clone();
+        let open = move || {
+            a.fetch_add(1, Ordering::Relaxed);
+            Err(Error::OpenFailed("not found".to_string()))
+        };
+        let policy = ReconnectPolicy {
+            retries: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        };
+
+        let deck = StreamDeck::with_transport(MockTransport::new(), Kind::Mini);
+        let mut deck = ReconnectingStreamDeck::with_opener(deck, Box::new(open), policy);
+        assert!(matches!(deck.reconnect(), Err(Error::OpenFailed(_))));
+        assert_eq!(attempts.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    #[cfg(feature = "hidapi")]
+    fn reconnect_keeps_claim() {
+        let key = "mock-reconnect-claim";
+        let open = move || {
+            // The claim is released while reopening
+            drop(DeviceClaim::new(key)?);
+            Err(Error::OpenFailed("not found".to_string()))
+        };
+        let policy = ReconnectPolicy {
+            retries: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        };
+
+        let mut deck = StreamDeck::with_transport(MockTransport::new(), Kind::Mini);
+        deck.claim = Some(DeviceClaim::new(key).unwrap());
+        let mut deck = ReconnectingStreamDeck::with_opener(deck, Box::new(open), policy);
+
+        assert!(matches!(deck.reconnect(), Err(Error::OpenFailed(_))));
+        assert!(matches!(DeviceClaim::new(key), Err(Error::AlreadyOpen)));
+    }
+
+    #[test]
+    fn reconnect_keeps_open_frame() {
+        let kind = Kind::Mini;
+        let mock = MockTransport::new();
+        let m = mock.clone();
+        let open = move || Ok(StreamDeck::with_transport(m.clone(), kind));
+
+        let deck = StreamDeck::with_transport(MockTransport::new(), kind);
+        let mut deck =
+            ReconnectingStreamDeck::with_opener(deck, Box::new(open), ReconnectPolicy::default());
+        deck.set_button_rgb(1, Colour::RED).unwrap();
+        deck.set_button_rgb(2, Colour::RED).unwrap();
+
+        deck.deck().begin_frame();
+        deck.set_button_rgb(2, Colour::GREEN).unwrap();
+        deck.reconnect().unwrap();
+
+        // Only the key outside the frame is restored, with the frame left open
+        let expected = MockTransport::new();
+        let mut other = StreamDeck::with_transport(expected.clone(), kind);
+        other.set_button_rgb(1, Colour::RED).unwrap();
+        assert_eq!(mock.written_packets(), expected.written_packets());
+
+        deck.flush().unwrap();
+        other.set_button_rgb(2, Colour::GREEN).unwrap();
+        assert_eq!(mock.written_packets(), expected.written_packets());
+    }
+}
@@ -0,0 +1,67 @@
+//! Process wide registry of open devices, used to reject opening a device twice
+//!
+//! Devices are expected to be opened once per process, with [SharedStreamDeck](crate::SharedStreamDeck)
+//! used to share a device between components or threads.
+
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+use crate::Error;
+
+static OPEN: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+fn open() -> std::sync::MutexGuard<'static, BTreeSet<String>> {
+    OPEN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Claim on an open device, released on drop
+#[derive(Debug)]
+pub(crate) struct DeviceClaim {
+    key: String,
+}
+
+impl DeviceClaim {
+    /// Claim the device identified by `key` (ie. the device path),
+    /// failing with [Error::AlreadyOpen] if this is already claimed
+    pub(crate) fn new(key: &str) -> Result<Self, Error> {
+        if !open().insert(key.to_string()) {
+            return Err(Error::AlreadyOpen);
+        }
+
+        Ok(Self {
+            key: key.to_string(),
+        })
+    }
+
+    /// Fetch the key identifying the claimed device
+    pub(crate) fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl Drop for DeviceClaim {
+    fn drop(&mut self) {
+        open().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Kind, MockTransport, StreamDeck};
+
+    #[test]
+    fn double_open() {
+        let key = "mock-double-open";
+
+        let mut deck = StreamDeck::with_transport(MockTransport::new(), Kind::Mini);
+        deck.claim = Some(DeviceClaim::new(key).unwrap());
+
+        assert!(matches!(DeviceClaim::new(key), Err(Error::AlreadyOpen)));
+        assert!(DeviceClaim::new("mock-other").is_ok());
+
+        // Closing the device releases the claim
+        drop(deck);
+        assert!(DeviceClaim::new(key).is_ok());
+    }
+}
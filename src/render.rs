@@ -0,0 +1,359 @@
+//! Button image rendering helpers
+
+use ab_glyph::{FontRef, PxScale};
+#[cfg(feature = "text")]
+use image::{imageops, imageops::FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut, draw_text_mut, text_size};
+use imageproc::rect::Rect;
+
+use crate::{Colour, Kind};
+
+/// Options for [render_icon_label]
+#[cfg(feature = "text")]
+pub struct IconLabelOptions {
+    foreground: Colour,
+    background: Colour,
+    scale: PxScale,
+    split: f32,
+}
+
+#[cfg(feature = "text")]
+impl IconLabelOptions {
+    /// Create icon and label options, where `split` is the fraction (0.0 to 1.0) of the
+    /// image height used for the icon, with the remainder used for the label
+    pub fn new(foreground: Colour, background: Colour, scale: PxScale, split: f32) -> Self {
+        IconLabelOptions {
+            foreground,
+            background,
+            scale,
+            split: split.clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[cfg(feature = "text")]
+impl Default for IconLabelOptions {
+    /// default is white 14 pixel high text on a black background,
+    /// with the icon using the top 70% of the image
+    fn default() -> Self {
+        IconLabelOptions {
+            foreground: Colour::WHITE,
+            background: Colour::BLACK,
+            scale: PxScale { x: 14.0, y: 14.0 },
+            split: 0.7,
+        }
+    }
+}
+
+/// Render an icon with a text label below, sized for the provided device kind
+///
+/// The icon is scaled to fit the top region, keeping the aspect ratio, and the label
+/// is centred in the bottom region. The result can be uploaded with
+/// [StreamDeck::set_button_image](crate::StreamDeck::set_button_image).
+#[cfg(feature = "text")]
+pub fn render_icon_label(
+    kind: Kind,
+    icon: &DynamicImage,
+    label: &str,
+    font: &FontRef,
+    opts: &IconLabelOptions,
+) -> DynamicImage {
+    let (w, h) = kind.image_size();
+    let (w, h) = (w as u32, h as u32);
+    let icon_h = icon_height(h, opts.split);
+
+    let bg = &opts.background;
+    let mut image = RgbaImage::from_pixel(w, h, Rgba([bg.r, bg.g, bg.b, 255]));
+
+    if icon_h > 0 {
+        let icon = icon.resize(w, icon_h, FilterType::Gaussian).to_rgba8();
+        let x = (w - icon.width()) / 2;
+        let y = (icon_h - icon.height()) / 2;
+        imageops::overlay(&mut image, &icon, x as i64, y as i64);
+    }
+
+    if !label.is_empty() {
+        let (tw, th) = text_size(opts.scale, font, label);
+        let x = (w as i32 - tw as i32) / 2;
+        let y = icon_h as i32 + (h as i32 - icon_h as i32 - th as i32) / 2;
+
+        let fg = &opts.foreground;
+        draw_text_mut(
+            &mut image,
+            Rgba([fg.r, fg.g, fg.b, 255]),
+            x,
+            y,
+            opts.scale,
+            font,
+            label,
+        );
+    }
+
+    DynamicImage::ImageRgba8(image).to_rgb8().into()
+}
+
+/// Digit rendering for [render_number]
+pub enum DigitStyle<'a> {
+    /// Digits drawn with the provided font
+    Font(&'a FontRef<'a>),
+    /// Seven segment style digits, supporting `0-9`, `-`, `:`, `.` and spaces
+    SevenSegment,
+}
+
+/// Options for [render_number]
+pub struct NumberOptions<'a> {
+    digits: DigitStyle<'a>,
+    foreground: Colour,
+    background: Colour,
+    label: Option<(String, &'a FontRef<'a>, PxScale)>,
+}
+
+impl<'a> NumberOptions<'a> {
+    pub fn new(digits: DigitStyle<'a>, foreground: Colour, background: Colour) -> Self {
+        NumberOptions {
+            digits,
+            foreground,
+            background,
+            label: None,
+        }
+    }
+
+    /// Set a label, drawn below the value
+    pub fn with_label(mut self, label: &str, font: &'a FontRef<'a>, scale: PxScale) -> Self {
+        self.label = Some((label.to_string(), font, scale));
+        self
+    }
+}
+
+/// Render a number (or any short value, such as a time) sized for the provided device kind
+///
+/// The value is scaled to the largest size that fits the key, above the label where
+/// one is set. Rendering is cheap enough to update a key each second, for clocks or
+/// counters driven by the caller.
+pub fn render_number(kind: Kind, value: &str, opts: &NumberOptions) -> DynamicImage {
+    let (w, h) = kind.image_size();
+    let (w, h) = (w as u32, h as u32);
+
+    let bg = &opts.background;
+    let fg = &opts.foreground;
+    let fg = Rgba([fg.r, fg.g, fg.b, 255]);
+    let mut image = RgbaImage::from_pixel(w, h, Rgba([bg.r, bg.g, bg.b, 255]));
+
+    // Reserve space for the label at the bottom of the key
+    let mut value_h = h;
+    if let Some((label, font, scale)) = &opts.label {
+        let (tw, th) = text_size(*scale, *font, label);
+        let y = h.saturating_sub(th + h / 16);
+        draw_text_mut(
+            &mut image,
+            fg,
+            (w as i32 - tw as i32) / 2,
+            y as i32,
+            *scale,
+            *font,
+            label,
+        );
+        value_h = y;
+    }
+
+    // Fit the value within a margin of the available region
+    let (max_w, max_h) = (w as f32 * 0.9, value_h as f32 * 0.9);
+
+    match opts.digits {
+        DigitStyle::Font(font) => {
+            let (tw, th) = text_size(PxScale::from(max_h), font, value);
+            let ratio = (max_w / tw.max(1) as f32).min(max_h / th.max(1) as f32);
+            let scale = PxScale::from(max_h * ratio.min(1.0));
+
+            let (tw, th) = text_size(scale, font, value);
+            let x = (w as i32 - tw as i32) / 2;
+            let y = (value_h as i32 - th as i32) / 2;
+            draw_text_mut(&mut image, fg, x, y, scale, font, value);
+        }
+        DigitStyle::SevenSegment => {
+            // Digit cells are a little over half as wide as they are high, with narrow separators
+            let widths: Vec<f32> = value
+                .chars()
+                .map(|c| if c == ':' || c == '.' { 0.25 } else { 0.6 })
+                .collect();
+            let total: f32 = widths.iter().sum();
+            let ch = max_h.min(max_w / total.max(0.25));
+
+            let mut x = (w as f32 - total * ch) / 2.0;
+            let y = (value_h as f32 - ch) / 2.0;
+            for (c, cw) in value.chars().zip(widths) {
+                draw_segment_char(&mut image, c, x, y, cw * ch, ch, fg);
+                x += cw * ch;
+            }
+        }
+    }
+
+    DynamicImage::ImageRgba8(image).to_rgb8().into()
+}
+
+/// Segments (a to g, clockwise from the top with g in the middle) lit for a character
+fn segments(c: char) -> u8 {
+    match c {
+        '0' => 0b0111111,
+        '1' => 0b0000110,
+        '2' => 0b1011011,
+        '3' => 0b1001111,
+        '4' => 0b1100110,
+        '5' => 0b1101101,
+        '6' => 0b1111101,
+        '7' => 0b0000111,
+        '8' => 0b1111111,
+        '9' => 0b1101111,
+        '-' => 0b1000000,
+        _ => 0,
+    }
+}
+
+/// Draw a seven segment style character into a cell of the provided size
+fn draw_segment_char(
+    image: &mut RgbaImage,
+    c: char,
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    colour: Rgba<u8>,
+) {
+    let t = (h / 10.0).max(1.0);
+    let mut rect = |x: f32, y: f32, w: f32, h: f32| {
+        let r = Rect::at(x.round() as i32, y.round() as i32)
+            .of_size(w.round().max(1.0) as u32, h.round().max(1.0) as u32);
+        draw_filled_rect_mut(image, r, colour);
+    };
+
+    match c {
+        ':' => {
+            rect(x + (w - t) / 2.0, y + h / 3.0 - t / 2.0, t, t);
+            rect(x + (w - t) / 2.0, y + h * 2.0 / 3.0 - t / 2.0, t, t);
+            return;
+        }
+        '.' => {
+            rect(x + (w - t) / 2.0, y + h - t, t, t);
+            return;
+        }
+        _ => (),
+    }
+
+    // Leave a gap between cells
+    let w = w - t;
+    let half = h / 2.0;
+    let lit = segments(c);
+    let parts = [
+        (t, 0.0, w - 2.0 * t, t),
+        (w - t, t, t, half - t),
+        (w - t, half, t, half - t),
+        (t, h - t, w - 2.0 * t, t),
+        (0.0, half, t, half - t),
+        (0.0, t, t, half - t),
+        (t, half - t / 2.0, w - 2.0 * t, t),
+    ];
+    for (i, (sx, sy, sw, sh)) in parts.iter().enumerate() {
+        if lit & (1 << i) != 0 {
+            rect(x + sx, y + sy, *sw, *sh);
+        }
+    }
+}
+
+/// Placeholder shown in place of images that fail to load,
+/// see [StreamDeck::set_missing_image_placeholder](crate::StreamDeck::set_missing_image_placeholder)
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaceholderStyle {
+    /// Red cross on a black background
+    Cross,
+    /// Solid colour
+    Colour(Colour),
+}
+
+/// Render a placeholder image sized for the provided device kind
+pub fn render_placeholder(kind: Kind, style: &PlaceholderStyle) -> DynamicImage {
+    let (w, h) = kind.image_size();
+    let (w, h) = (w as u32, h as u32);
+
+    let image = match style {
+        PlaceholderStyle::Colour(c) => RgbaImage::from_pixel(w, h, Rgba([c.r, c.g, c.b, 255])),
+        PlaceholderStyle::Cross => {
+            let mut image = RgbaImage::from_pixel(w, h, Rgba([0, 0, 0, 255]));
+            let red = Rgba([255, 0, 0, 255]);
+            let (x0, y0, x1, y1) = (
+                w as f32 * 0.2,
+                h as f32 * 0.2,
+                w as f32 * 0.8,
+                h as f32 * 0.8,
+            );
+
+            // Lines are a single pixel wide, so offset copies for a thicker cross
+            for o in -2..=2 {
+                let o = o as f32;
+                draw_line_segment_mut(&mut image, (x0 + o, y0), (x1 + o, y1), red);
+                draw_line_segment_mut(&mut image, (x0 + o, y1), (x1 + o, y0), red);
+            }
+            image
+        }
+    };
+
+    DynamicImage::ImageRgba8(image).to_rgb8().into()
+}
+
+/// Height of the icon region for the provided image height and split
+#[cfg(feature = "text")]
+fn icon_height(height: u32, split: f32) -> u32 {
+    ((height as f32 * split).round() as u32).min(height)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn placeholders() {
+        let kind = Kind::Mini;
+        let (w, h) = kind.image_size();
+
+        let image = render_placeholder(kind, &PlaceholderStyle::Cross).to_rgb8();
+        assert_eq!(image.dimensions(), (w as u32, h as u32));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(w as u32 / 2, h as u32 / 2).0, [255, 0, 0]);
+
+        let image = render_placeholder(kind, &PlaceholderStyle::Colour(Colour::BLUE)).to_rgb8();
+        assert!(image.pixels().all(|p| p.0 == [0, 0, 255]));
+    }
+
+    #[test]
+    fn seven_segment_numbers() {
+        let kind = Kind::Xl;
+        let (w, h) = kind.image_size();
+        let opts = NumberOptions::new(DigitStyle::SevenSegment, Colour::WHITE, Colour::BLACK);
+
+        let lit = |value: &str| {
+            let image = render_number(kind, value, &opts).to_rgb8();
+            assert_eq!(image.dimensions(), (w as u32, h as u32));
+            image.pixels().filter(|p| p.0 == [255, 255, 255]).count()
+        };
+
+        assert_eq!(lit(""), 0);
+        assert_eq!(lit(" "), 0);
+        assert!(lit("1") > 0);
+        assert!(lit("8") > lit("1"));
+        assert!(lit("88") > lit("8"));
+        assert!(lit(":") > 0);
+        assert!(lit("-") > 0);
+
+        // Digits are scaled down to fit longer values
+        assert!(lit("8888") < lit("8") * 4);
+    }
+
+    #[test]
+    #[cfg(feature = "text")]
+    fn icon_regions() {
+        assert_eq!(icon_height(72, 0.7), 50);
+        assert_eq!(icon_height(96, 0.0), 0);
+        assert_eq!(icon_height(80, 1.0), 80);
+        assert_eq!(icon_height(80, IconLabelOptions::default().split), 56);
+    }
+}
@@ -0,0 +1,136 @@
+//! Thread safe device wrapper, for sharing a device between threads
+//!
+//! Each call locks the device for its duration, so reads should use a short
+//! timeout (or non-blocking mode) to avoid stalling writes from other threads.
+//! hidapi devices may be used from any thread but not concurrently, which the
+//! lock ensures.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use image::DynamicImage;
+
+use crate::{ButtonStates, Colour, Error, ImageOptions, InputReport, Kind, StreamDeck};
+
+/// Shared handle to a [StreamDeck], cloning the handle shares the same device
+#[derive(Clone)]
+pub struct SharedStreamDeck {
+    inner: Arc<Mutex<StreamDeck>>,
+}
+
+impl SharedStreamDeck {
+    /// Create a shared handle from a connected device
+    pub fn new(deck: StreamDeck) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(deck)),
+        }
+    }
+
+    /// Lock the device for a sequence of calls
+    pub fn lock(&self) -> MutexGuard<'_, StreamDeck> {
+        // A panic in another thread leaves the device itself usable
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run the provided closure with the device locked
+    pub fn with<R, F: FnOnce(&mut StreamDeck) -> R>(&self, f: F) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Fetch the device kind
+    pub fn kind(&self) -> Kind {
+        self.lock().kind()
+    }
+
+    /// See [StreamDeck::read_buttons]
+    pub fn read_buttons(&self, timeout: Option<Duration>) -> Result<ButtonStates, Error> {
+        self.lock().read_buttons(timeout)
+    }
+
+    /// See [StreamDeck::read_input_report]
+    pub fn read_input_report(&self, timeout: Option<Duration>) -> Result<InputReport, Error> {
+        self.lock().read_input_report(timeout)
+    }
+
+    /// See [StreamDeck::set_brightness]
+    pub fn set_brightness(&self, brightness: u8) -> Result<(), Error> {
+        self.lock().set_brightness(brightness)
+    }
+
+    /// See [StreamDeck::set_button_rgb]
+    pub fn set_button_rgb(&self, key: u8, colour: impl Into<Colour>) -> Result<(), Error> {
+        self.lock().set_button_rgb(key, colour)
+    }
+
+    /// See [StreamDeck::set_button_image]
+    pub fn set_button_image(&self, key: u8, image: DynamicImage) -> Result<(), Error> {
+        self.lock().set_button_image(key, image)
+    }
+
+    /// See [StreamDeck::set_button_file]
+    pub fn set_button_file(&self, key: u8, path: &str, opts: &ImageOptions) -> Result<(), Error> {
+        self.lock().set_button_file(key, path, opts)
+    }
+
+    /// See [StreamDeck::flush]
+    pub fn flush(&self) -> Result<(), Error> {
+        self.lock().flush()
+    }
+}
+
+impl From<StreamDeck> for SharedStreamDeck {
+    fn from(deck: StreamDeck) -> Self {
+        Self::new(deck)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockTransport;
+
+    #[test]
+    fn read_and_write_from_threads() {
+        let mock = MockTransport::new();
+        let deck = SharedStreamDeck::new(StreamDeck::with_transport(mock.clone(), Kind::Mini));
+
+        for i in 0..6 {
+            let mut report = vec![0x01, 0, 0, 0, 0, 0, 0];
+            report[1 + i] = 1;
+            mock.push_input(&report);
+        }
+
+        let reader = {
+            let deck = deck.clone();
+            std::thread::spawn(move || {
+                let mut pressed = Vec::new();
+                while pressed.len() < 6 {
+                    match deck.read_buttons(Some(Duration::from_millis(1))) {
+                        Ok(b) => pressed.extend(b.pressed_indices()),
+                        Err(Error::NoData) => (),
+                        Err(e) => panic!("{:?}", e),
+                    }
+                }
+                pressed
+            })
+        };
+
+        let writer = {
+            let deck = deck.clone();
+            std::thread::spawn(move || {
+                for key in 0..6 {
+                    deck.set_button_rgb(key, &Colour::RED).unwrap();
+                }
+            })
+        };
+
+        assert_eq!(reader.join().unwrap(), vec![0, 1, 2, 3, 4, 5]);
+        writer.join().unwrap();
+
+        // Each upload is written whole, without interleaving
+        let keys: Vec<u8> = mock.written_packets().iter().map(|p| p[5]).collect();
+        let mut uploads = keys.clone();
+        uploads.dedup();
+        assert_eq!(uploads, vec![0, 1, 2, 3, 4, 5]);
+    }
+}
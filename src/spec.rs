@@ -0,0 +1,414 @@
+//! Device descriptions, see [DeviceSpec]
+//!
+//! Each [Kind] is described by one of the specifications in this module, which may be
+//! used as a base for describing other compatible hardware with [Kind::Custom].
+
+use crate::info::{MINI_IMAGE_BASE, ORIGINAL_IMAGE_BASE};
+use crate::{ImageMode, KeyDirection, Kind, Mirroring, Rotation};
+
+/// Device geometry and protocol description
+///
+/// Hardware not otherwise supported (such as clones of supported devices) may be
+/// described by a static specification, based on that of the closest supported device:
+///
+/// ```
+/// use streamdeck::{spec, DeviceSpec, Kind};
+///
+/// static CLONE: DeviceSpec = spec::XL
+///     .with_name("Stream Deck Clone")
+///     .with_keys(15, 5)
+///     .with_image_size(72, 72);
+///
+/// let kind = Kind::Custom(&CLONE);
+/// assert_eq!(kind.keys(), 15);
+/// ```
+///
+/// The kind may then be used with [StreamDeck::with_transport](crate::StreamDeck::with_transport).
+///
+/// Fields may be added as further devices are supported, so specifications are built
+/// from an existing specification with the `with_*` methods.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct DeviceSpec {
+    /// Product name
+    pub name: &'static str,
+    /// Number of keys
+    pub keys: u8,
+    /// Number of key columns
+    pub columns: u8,
+    /// Number of touch points, reported as buttons following the keys
+    pub touch_points: u8,
+    /// Number of dials
+    pub dials: u8,
+    /// Offset for the first key in button reports (after the report ID)
+    pub key_data_offset: usize,
+    /// Order of keys in input reports and image writes
+    pub key_direction: KeyDirection,
+    /// Offset added to key indices in image writes
+    pub key_index_offset: u8,
+    /// Key image size in pixels (width, height), zero for devices without a display
+    pub image_size: (usize, usize),
+    /// Key image format
+    pub image_mode: ImageMode,
+    /// Rotation applied to key images
+    pub image_rotation: Rotation,
+    /// Mirroring applied to key images
+    pub image_mirror: Mirroring,
+    /// Header prefixing key image data, such as a BMP header
+    ///
+    /// BMP image data is sent without row padding, so BMP devices need a width that is a
+    /// multiple of 4 pixels (with images for other widths rejected with
+    /// [Error::UnalignedBmpWidth](crate::Error::UnalignedBmpWidth)) and a header matching
+    /// the image size.
+    pub image_base: &'static [u8],
+    /// Length of each image upload packet
+    pub image_report_len: usize,
+    /// Info bar screen size in pixels (width, height), for devices with an info bar
+    pub info_bar_size: Option<(usize, usize)>,
+    /// Usable range of raw device brightness values, `(0, 100)` for all known devices
+    pub brightness_range: (u8, u8),
+    /// Whether the device uses the second generation protocol, with JPEG images,
+    /// typed input reports and 8 byte image report headers
+    pub v2: bool,
+}
+
+impl DeviceSpec {
+    /// Wrap the specification as a [Kind]
+    pub const fn kind(&'static self) -> Kind {
+        Kind::Custom(self)
+    }
+
+    /// Set the product name
+    pub const fn with_name(self, name: &'static str) -> Self {
+        Self { name, ..self }
+    }
+
+    /// Set the number of keys and key columns
+    pub const fn with_keys(self, keys: u8, columns: u8) -> Self {
+        Self {
+            keys,
+            columns,
+            ..self
+        }
+    }
+
+    /// Set the number of touch points
+    pub const fn with_touch_points(self, touch_points: u8) -> Self {
+        Self {
+            touch_points,
+            ..self
+        }
+    }
+
+    /// Set the number of dials
+    pub const fn with_dials(self, dials: u8) -> Self {
+        Self { dials, ..self }
+    }
+
+    /// Set the layout of keys in input reports and image writes
+    pub const fn with_key_layout(
+        self,
+        key_data_offset: usize,
+        key_direction: KeyDirection,
+        key_index_offset: u8,
+    ) -> Self {
+        Self {
+            key_data_offset,
+            key_direction,
+            key_index_offset,
+            ..self
+        }
+    }
+
+    /// Set the key image size in pixels
+    pub const fn with_image_size(self, width: usize, height: usize) -> Self {
+        Self {
+            image_size: (width, height),
+            ..self
+        }
+    }
+
+    /// Set the key image format and header, see [DeviceSpec::image_base]
+    pub const fn with_image_format(self, mode: ImageMode, base: &'static [u8]) -> Self {
+        Self {
+            image_mode: mode,
+            image_base: base,
+            ..self
+        }
+    }
+
+    /// Set the transformation applied to key images
+    pub const fn with_image_transform(self, rotation: Rotation, mirror: Mirroring) -> Self {
+        Self {
+            image_rotation: rotation,
+            image_mirror: mirror,
+            ..self
+        }
+    }
+
+    /// Set the length of each image upload packet
+    pub const fn with_image_report_len(self, image_report_len: usize) -> Self {
+        Self {
+            image_report_len,
+            ..self
+        }
+    }
+
+    /// Set the info bar screen size in pixels, or `None` for devices without an info bar
+    pub const fn with_info_bar_size(self, info_bar_size: Option<(usize, usize)>) -> Self {
+        Self {
+            info_bar_size,
+            ..self
+        }
+    }
+
+    /// Set the usable range of raw device brightness values
+    ///
+    /// Panics (failing compilation for constant specifications) if `min` exceeds `max`.
+    pub const fn with_brightness_range(self, min: u8, max: u8) -> Self {
+        assert!(min <= max, "brightness range minimum exceeds maximum");
+        Self {
+            brightness_range: (min, max),
+            ..self
+        }
+    }
+
+    /// Set whether the device uses the second generation protocol
+    pub const fn with_v2(self, v2: bool) -> Self {
+        Self { v2, ..self }
+    }
+}
+
+/// Original Stream Deck (15 keys), with BMP images
+pub const ORIGINAL: DeviceSpec = DeviceSpec {
+    name: "Stream Deck",
+    keys: 15,
+    columns: 5,
+    touch_points: 0,
+    dials: 0,
+    key_data_offset: 0,
+    key_direction: KeyDirection::RightToLeft,
+    key_index_offset: 0,
+    image_size: (72, 72),
+    image_mode: ImageMode::Bmp,
+    image_rotation: Rotation::Rot0,
+    // The image is flipped across the Y axis
+    image_mirror: Mirroring::Y,
+    image_base: &ORIGINAL_IMAGE_BASE,
+    image_report_len: 8191,
+    info_bar_size: None,
+    brightness_range: (0, 100),
+    v2: false,
+};
+
+/// Revised original Stream Deck (15 keys), using the second generation protocol
+pub const ORIGINAL_V2: DeviceSpec = DeviceSpec {
+    name: "Stream Deck Original",
+    key_data_offset: 3,
+    key_direction: KeyDirection::LeftToRight,
+    image_mode: ImageMode::Jpeg,
+    // Both X and Y need to flip
+    image_mirror: Mirroring::Both,
+    image_base: &[],
+    image_report_len: 1024,
+    v2: true,
+    ..ORIGINAL
+};
+
+/// Stream Deck Mini (6 keys)
+pub const MINI: DeviceSpec = DeviceSpec {
+    name: "Stream Deck Mini",
+    keys: 6,
+    columns: 3,
+    touch_points: 0,
+    dials: 0,
+    key_data_offset: 0,
+    key_direction: KeyDirection::LeftToRight,
+    key_index_offset: 0,
+    image_size: (80, 80),
+    image_mode: ImageMode::Bmp,
+    // Rotated, not mirrored
+    image_rotation: Rotation::Rot270,
+    image_mirror: Mirroring::None,
+    image_base: &MINI_IMAGE_BASE,
+    image_report_len: 1024,
+    info_bar_size: None,
+    brightness_range: (0, 100),
+    v2: false,
+};
+
+/// Revised Stream Deck Mini, with key indices offset by one in image writes
+pub const REVISED_MINI: DeviceSpec = DeviceSpec {
+    key_index_offset: 1,
+    ..MINI
+};
+
+/// Stream Deck XL (32 keys)
+pub const XL: DeviceSpec = DeviceSpec {
+    name: "Stream Deck XL",
+    keys: 32,
+    columns: 8,
+    image_size: (96, 96),
+    ..ORIGINAL_V2
+};
+
+/// Stream Deck MK.2 (15 keys)
+pub const MK2: DeviceSpec = DeviceSpec {
+    name: "Stream Deck MK.2",
+    ..ORIGINAL_V2
+};
+
+/// Stream Deck Pedal (3 pedals, no display)
+pub const PEDAL: DeviceSpec = DeviceSpec {
+    name: "Stream Deck Pedal",
+    keys: 3,
+    columns: 3,
+    image_size: (0, 0),
+    image_mirror: Mirroring::None,
+    ..ORIGINAL_V2
+};
+
+/// Stream Deck Neo (8 keys), with two touch points and an info bar
+pub const NEO: DeviceSpec = DeviceSpec {
+    name: "Stream Deck Neo",
+    keys: 8,
+    columns: 4,
+    touch_points: 2,
+    image_size: (96, 96),
+    info_bar_size: Some((248, 58)),
+    ..ORIGINAL_V2
+};
+
+/// Stream Deck Studio (32 keys), with two dials
+pub const STUDIO: DeviceSpec = DeviceSpec {
+    name: "Stream Deck Studio",
+    keys: 32,
+    columns: 16,
+    dials: 2,
+    image_size: (144, 112),
+    ..ORIGINAL_V2
+};
+
+/// Stream Deck Module 6, as for the Mini
+pub const MODULE_6: DeviceSpec = DeviceSpec {
+    name: "Stream Deck Module 6",
+    ..MINI
+};
+
+/// Stream Deck Module 15, as for the MK.2
+pub const MODULE_15: DeviceSpec = DeviceSpec {
+    name: "Stream Deck Module 15",
+    ..MK2
+};
+
+/// Stream Deck Module 32, as for the XL
+pub const MODULE_32: DeviceSpec = DeviceSpec {
+    name: "Stream Deck Module 32",
+    ..XL
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Error, MockTransport, StreamDeck};
+    use image::DynamicImage;
+
+    static CLONE: DeviceSpec = XL
+        .with_name("Clone")
+        .with_keys(6, 3)
+        .with_image_size(80, 80);
+
+    static DIM: DeviceSpec = MINI.with_brightness_range(10, 60);
+
+    #[test]
+    fn custom_spec() {
+        let kind = CLONE.kind();
+        assert_eq!(kind, Kind::Custom(&CLONE));
+        assert_eq!(kind.name(), "Clone");
+        assert_eq!(kind.keys(), 6);
+        assert_eq!(kind.input_report_size(), 10);
+        assert_eq!(kind.image_mode(), ImageMode::Jpeg);
+        assert!(kind.is_v2());
+
+        // Custom devices are driven with the protocol of the base device
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), kind);
+        deck.set_button_image(5, DynamicImage::new_rgb8(80, 80))
+            .unwrap();
+        assert_eq!(mock.written_packets()[0][..3], [0x02, 0x07, 5]);
+
+        mock.push_input(&[0x01, 0x00, 0x06, 0x00, 0, 0, 0, 1, 0, 0]);
+        assert!(deck.read_buttons(None).unwrap().is_pressed(3));
+    }
+
+    #[test]
+    fn builder() {
+        let built = ORIGINAL
+            .with_name("Stream Deck Original")
+            .with_key_layout(3, KeyDirection::LeftToRight, 0)
+            .with_image_format(ImageMode::Jpeg, &[])
+            .with_image_transform(Rotation::Rot0, Mirroring::Both)
+            .with_image_report_len(1024)
+            .with_v2(true);
+        assert_eq!(built, ORIGINAL_V2);
+
+        let built = ORIGINAL_V2
+            .with_name("Stream Deck Neo")
+            .with_keys(8, 4)
+            .with_touch_points(2)
+            .with_image_size(96, 96)
+            .with_info_bar_size(Some((248, 58)));
+        assert_eq!(built, NEO);
+        assert_eq!(
+            STUDIO,
+            XL.with_name("Stream Deck Studio")
+                .with_keys(32, 16)
+                .with_dials(2)
+                .with_image_size(144, 112)
+        );
+    }
+
+    #[test]
+    fn unaligned_bmp_width() {
+        static NARROW: DeviceSpec = MINI.with_image_size(70, 70);
+
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), NARROW.kind());
+        assert!(matches!(
+            deck.set_button_rgb(0, crate::Colour::RED),
+            Err(Error::UnalignedBmpWidth { width: 70 })
+        ));
+        assert!(mock.written_packets().is_empty());
+
+        // JPEG images are not affected
+        static NARROW_JPEG: DeviceSpec = XL.with_image_size(70, 70);
+        let mut deck = StreamDeck::with_transport(MockTransport::new(), NARROW_JPEG.kind());
+        deck.set_button_rgb(0, crate::Colour::RED).unwrap();
+    }
+
+    #[test]
+    fn custom_brightness_range() {
+        let mock = MockTransport::new();
+        let mut deck = StreamDeck::with_transport(mock.clone(), DIM.kind());
+        for percent in [0, 50, 100] {
+            deck.set_brightness(percent).unwrap();
+        }
+
+        let raw: Vec<u8> = mock.sent_feature_reports().iter().map(|r| r[5]).collect();
+        assert_eq!(raw, [10, 35, 60]);
+    }
+
+    #[test]
+    fn inverted_brightness_range() {
+        assert!(std::panic::catch_unwind(|| MINI.with_brightness_range(60, 10)).is_err());
+
+        // Specifications declared within the crate bypass the check, and clamp to the minimum
+        static INVERTED: DeviceSpec = DeviceSpec {
+            brightness_range: (60, 10),
+            ..MINI
+        };
+        for percent in [0, 50, 100] {
+            assert_eq!(INVERTED.kind().brightness_raw(percent), 60);
+        }
+    }
+}
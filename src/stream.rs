@@ -0,0 +1,142 @@
+//! Input events as a [Stream], for polling devices alongside other async sources
+//!
+//! This requires the `stream` feature.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+
+use crate::input::listen;
+use crate::{Error, InputEvent, InputState, SharedStreamDeck};
+
+#[derive(Default)]
+struct State {
+    events: VecDeque<Result<InputEvent, Error>>,
+    done: bool,
+    waker: Option<Waker>,
+}
+
+type Shared = Arc<(Mutex<State>, AtomicBool)>;
+
+fn lock(shared: &Shared) -> MutexGuard<'_, State> {
+    shared.0.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Stream of input events from a shared device, read from a background thread
+///
+/// Events are decoded as by [InputListener](crate::InputListener), with the same read
+/// timeout trade-off for writes from other threads. A device error is returned as the
+/// final item of the stream. The reader thread exits once the stream is dropped.
+pub struct InputStream {
+    shared: Shared,
+}
+
+impl InputStream {
+    /// Start reading from the provided device, with each read waiting up to `timeout`
+    pub fn new(deck: SharedStreamDeck, timeout: Duration) -> Self {
+        let state = InputState::new(deck.lock().kind().buttons(), Instant::now());
+        Self::with_state(deck, state, timeout)
+    }
+
+    /// Start reading from the provided device, continuing from existing state
+    pub fn with_state(deck: SharedStreamDeck, state: InputState, timeout: Duration) -> Self {
+        let shared: Shared = Arc::new((Mutex::new(State::default()), AtomicBool::new(false)));
+
+        let s = shared.clone();
+        std::thread::spawn(move || {
+            let r = listen(deck, state, timeout, &s.1, |e| {
+                let mut state = lock(&s);
+                state.events.push_back(Ok(e));
+                if let Some(w) = state.waker.take() {
+                    w.wake();
+                }
+            });
+
+            let mut state = lock(&s);
+            if let Err(e) = r {
+                state.events.push_back(Err(e));
+            }
+            state.done = true;
+            if let Some(w) = state.waker.take() {
+                w.wake();
+            }
+        });
+
+        Self { shared }
+    }
+}
+
+impl Stream for InputStream {
+    type Item = Result<InputEvent, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = lock(&self.shared);
+
+        match state.events.pop_front() {
+            Some(item) => Poll::Ready(Some(item)),
+            None if state.done => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for InputStream {
+    fn drop(&mut self) {
+        self.shared.1.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ButtonAction, Kind, MockTransport, StreamDeck};
+    use std::sync::atomic::AtomicUsize;
+    use std::task::Wake;
+
+    struct CountWakes(AtomicUsize);
+
+    impl Wake for CountWakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn input_stream() {
+        let mock = MockTransport::new();
+        let deck = SharedStreamDeck::new(StreamDeck::with_transport(mock.clone(), Kind::Mini));
+
+        let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut stream = InputStream::new(deck, Duration::from_millis(1));
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+        mock.push_input(&[0x01, 0, 0, 0, 0, 1, 0]);
+
+        // Poll until the event arrives, which wakes the task
+        let event = loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(e) => break e,
+                Poll::Pending => std::thread::sleep(Duration::from_millis(1)),
+            }
+        };
+        assert_eq!(
+            event.unwrap().unwrap(),
+            InputEvent::Button {
+                index: 4,
+                action: ButtonAction::Pressed
+            }
+        );
+        assert!(wakes.0.load(Ordering::Relaxed) > 0);
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+    }
+}
@@ -0,0 +1,206 @@
+//! Device transports
+//!
+//! [DeckTransport] abstracts the HID operations used by [StreamDeck](crate::StreamDeck),
+//! allowing a device to be driven over something other than hidapi (for example WebHID,
+//! a network proxy to a remote device, or a mock for testing).
+//! With the `hidapi` feature (enabled by default) this is implemented for [hidapi::HidDevice].
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::Error;
+
+/// Transport used to communicate with a device
+///
+/// Reports are passed through unmodified, including the leading report ID.
+/// Transport specific errors may be returned as [Error::Io].
+pub trait DeckTransport: Send {
+    /// Write an output report, returning the number of bytes written
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error>;
+
+    /// Read an input report into the provided buffer, returning the number of bytes read
+    ///
+    /// With a timeout this must return `Ok(0)` if no report is available within the timeout,
+    /// otherwise this blocks (or not) as configured by [DeckTransport::set_blocking_mode].
+    fn read(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> Result<usize, Error>;
+
+    /// Send a feature report
+    fn send_feature_report(&mut self, data: &[u8]) -> Result<(), Error>;
+
+    /// Fetch a feature report, with the report ID in the first byte of the buffer,
+    /// returning the number of bytes read
+    fn get_feature_report(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Set whether reads without a timeout block until data is available
+    fn set_blocking_mode(&mut self, blocking: bool) -> Result<(), Error>;
+
+    /// Fetch the device manufacturer string, if available
+    fn manufacturer(&mut self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    /// Fetch the device product string, if available
+    fn product(&mut self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    /// Fetch the device serial, if available
+    fn serial(&mut self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "hidapi")]
+impl DeckTransport for hidapi::HidDevice {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        Ok(hidapi::HidDevice::write(self, data)?)
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> Result<usize, Error> {
+        let n = match timeout {
+            Some(t) => self.read_timeout(buf, t.as_millis() as i32)?,
+            None => hidapi::HidDevice::read(self, buf)?,
+        };
+        Ok(n)
+    }
+
+    fn send_feature_report(&mut self, data: &[u8]) -> Result<(), Error> {
+        Ok(hidapi::HidDevice::send_feature_report(self, data)?)
+    }
+
+    fn get_feature_report(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        Ok(hidapi::HidDevice::get_feature_report(self, buf)?)
+    }
+
+    fn set_blocking_mode(&mut self, blocking: bool) -> Result<(), Error> {
+        Ok(hidapi::HidDevice::set_blocking_mode(self, blocking)?)
+    }
+
+    fn manufacturer(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.get_manufacturer_string()?)
+    }
+
+    fn product(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.get_product_string()?)
+    }
+
+    fn serial(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.get_serial_number_string()?)
+    }
+}
+
+/// In-memory transport for testing, recording all reports written to the device
+///
+/// Clones share state, so a clone can be kept to inspect the reports written
+/// once the transport has been passed to [StreamDeck::with_transport](crate::StreamDeck::with_transport).
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    written: Vec<Vec<u8>>,
+    features_sent: Vec<Vec<u8>>,
+    features: Vec<Vec<u8>>,
+    inputs: VecDeque<Vec<u8>>,
+    serial: Option<String>,
+    blocking: Option<bool>,
+}
+
+impl MockTransport {
+    /// Create a new mock transport
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        // State is only modified under the lock, so a poisoned lock is still consistent
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Fetch the output reports written to the device, in order
+    pub fn written_packets(&self) -> Vec<Vec<u8>> {
+        self.state().written.clone()
+    }
+
+    /// Fetch the feature reports sent to the device, in order
+    pub fn sent_feature_reports(&self) -> Vec<Vec<u8>> {
+        self.state().features_sent.clone()
+    }
+
+    /// Clear recorded output and feature reports
+    pub fn clear(&self) {
+        let mut s = self.state();
+        s.written.clear();
+        s.features_sent.clear();
+    }
+
+    /// Queue an input report (including the report ID) to be returned by a read
+    pub fn push_input(&self, report: &[u8]) {
+        self.state().inputs.push_back(report.to_vec());
+    }
+
+    /// Set the response to a feature report request, matched on the leading report ID
+    pub fn set_feature_report(&self, report: &[u8]) {
+        let mut s = self.state();
+        s.features.retain(|r| r.first() != report.first());
+        s.features.push(report.to_vec());
+    }
+
+    /// Fetch the blocking mode last set on the transport, if any
+    pub fn blocking_mode(&self) -> Option<bool> {
+        self.state().blocking
+    }
+
+    /// Set the serial reported by the transport
+    pub fn set_serial(&self, serial: &str) {
+        self.state().serial = Some(serial.to_string());
+    }
+}
+
+impl DeckTransport for MockTransport {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.state().written.push(data.to_vec());
+        Ok(data.len())
+    }
+
+    fn read(&mut self, buf: &mut [u8], _timeout: Option<Duration>) -> Result<usize, Error> {
+        let report = match self.state().inputs.pop_front() {
+            Some(r) => r,
+            None => return Ok(0),
+        };
+
+        let n = report.len().min(buf.len());
+        buf[..n].copy_from_slice(&report[..n]);
+        Ok(n)
+    }
+
+    fn send_feature_report(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.state().features_sent.push(data.to_vec());
+        Ok(())
+    }
+
+    fn get_feature_report(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let s = self.state();
+        let report = s
+            .features
+            .iter()
+            .find(|r| r.first() == buf.first())
+            .ok_or(Error::NoData)?;
+
+        let n = report.len().min(buf.len());
+        buf[..n].copy_from_slice(&report[..n]);
+        Ok(n)
+    }
+
+    fn set_blocking_mode(&mut self, blocking: bool) -> Result<(), Error> {
+        self.state().blocking = Some(blocking);
+        Ok(())
+    }
+
+    fn serial(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.state().serial.clone())
+    }
+}
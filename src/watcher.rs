@@ -0,0 +1,158 @@
+//! Device hotplug notifications, see [DeviceWatcher]
+
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use hidapi::HidApi;
+
+use crate::{DeviceInfo, Error, Kind, StreamDeck};
+
+/// Device attach and detach events, identifying devices by serial
+///
+/// Devices that do not report a serial are identified by their HID path instead.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceEvent {
+    /// A device of the provided kind was attached
+    DeviceAttached(Kind, String),
+    /// A previously attached device was detached
+    DeviceDetached(String),
+}
+
+/// Watches for devices being attached and detached, delivering [DeviceEvent]s over a channel
+///
+/// Devices are detected by HID enumeration (see [StreamDeck::list]) from a background
+/// thread at the provided interval, so events are delayed by up to the interval. This is
+/// used on all platforms, as hidapi does not expose hotplug notifications. Devices
+/// already attached when the watcher starts are reported as attached by the first scan.
+pub struct DeviceWatcher {
+    events: Receiver<DeviceEvent>,
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DeviceWatcher {
+    /// Start watching for devices, scanning at the provided interval
+    pub fn new(interval: Duration) -> Result<Self, Error> {
+        let api = HidApi::new()?;
+        let (tx, events) = channel();
+        let (stop, stopped) = channel();
+
+        let handle = std::thread::spawn(move || watch(api, interval, tx, stopped));
+
+        Ok(Self {
+            events,
+            stop: Some(stop),
+            handle: Some(handle),
+        })
+    }
+
+    /// Fetch the receiver for device events
+    pub fn events(&self) -> &Receiver<DeviceEvent> {
+        &self.events
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread
+        self.stop.take();
+
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+fn watch(mut api: HidApi, interval: Duration, events: Sender<DeviceEvent>, stop: Receiver<()>) {
+    let mut known = Vec::new();
+
+    loop {
+        match api.refresh_devices() {
+            Ok(()) => {
+                let current = StreamDeck::list_with_hid(&api);
+                for event in diff_devices(&known, &current) {
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+                known = current;
+            }
+            // Enumeration may fail transiently while devices are changing
+            Err(e) => warn!("Device enumeration failed: {}", e),
+        }
+
+        if let Err(RecvTimeoutError::Disconnected) = stop.recv_timeout(interval) {
+            return;
+        }
+    }
+}
+
+/// Identify a device by serial, falling back to the HID path
+fn device_id(device: &DeviceInfo) -> &str {
+    device.serial.as_deref().unwrap_or(&device.path)
+}
+
+/// Compare device lists by identifier, returning detach events followed by attach events
+fn diff_devices(previous: &[DeviceInfo], current: &[DeviceInfo]) -> Vec<DeviceEvent> {
+    let detached = previous
+        .iter()
+        .filter(|p| !current.iter().any(|c| device_id(c) == device_id(p)))
+        .map(|d| DeviceEvent::DeviceDetached(device_id(d).to_string()));
+
+    let attached = current
+        .iter()
+        .filter(|c| !previous.iter().any(|p| device_id(p) == device_id(c)))
+        .map(|d| DeviceEvent::DeviceAttached(d.kind, device_id(d).to_string()));
+
+    detached.chain(attached).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{pids, Kind, ELGATO_VID};
+
+    #[test]
+    fn device_changes() {
+        let device = |path: &str, pid: u16, kind: Kind| DeviceInfo {
+            vid: ELGATO_VID,
+            pid,
+            serial: Some(format!("SERIAL{}", path)),
+            path: path.to_string(),
+            kind,
+        };
+        let (mini, xl, mk2) = (
+            device("1", pids::MINI, Kind::Mini),
+            device("2", pids::XL, Kind::Xl),
+            device("3", pids::MK2, Kind::Mk2),
+        );
+        let attached = |kind, serial: &str| DeviceEvent::DeviceAttached(kind, serial.to_string());
+        let detached = |serial: &str| DeviceEvent::DeviceDetached(serial.to_string());
+
+        assert_eq!(
+            diff_devices(&[], &[mini.clone(), xl.clone()]),
+            vec![
+                attached(Kind::Mini, "SERIAL1"),
+                attached(Kind::Xl, "SERIAL2")
+            ]
+        );
+        assert!(diff_devices(&[mini.clone(), xl.clone()], &[xl.clone(), mini.clone()]).is_empty());
+        assert_eq!(
+            diff_devices(&[mini, xl.clone()], &[xl.clone(), mk2.clone()]),
+            vec![detached("SERIAL1"), attached(Kind::Mk2, "SERIAL3")]
+        );
+        assert_eq!(
+            diff_devices(&[xl, mk2], &[]),
+            vec![detached("SERIAL2"), detached("SERIAL3")]
+        );
+
+        // Devices without a serial fall back to the path
+        let mut unnamed = device("4", pids::MINI, Kind::Mini);
+        unnamed.serial = None;
+        assert_eq!(
+            diff_devices(&[], &[unnamed]),
+            vec![attached(Kind::Mini, "4")]
+        );
+    }
+}
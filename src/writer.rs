@@ -0,0 +1,176 @@
+//! Background image upload thread, see [StreamDeck::set_background_uploads](crate::StreamDeck::set_background_uploads)
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use crate::{upload_image, DeckTransport, DeviceImage, Error, Kind};
+
+/// Image upload waiting to be written
+struct Upload {
+    key: u8,
+    image: DeviceImage,
+    chunk_size: usize,
+}
+
+#[derive(Default)]
+struct State {
+    /// Pending uploads in write order, at most one per key
+    pending: Vec<Upload>,
+    /// Set while an upload is in progress
+    busy: bool,
+    /// First error since the last wait
+    error: Option<Error>,
+    shutdown: bool,
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+/// Writes queued button images to a shared device from a background thread
+pub(crate) struct BackgroundWriter {
+    shared: Shared,
+    handle: Option<JoinHandle<()>>,
+}
+
+fn lock(shared: &Shared) -> MutexGuard<'_, State> {
+    shared.0.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl BackgroundWriter {
+    pub(crate) fn new(device: Arc<Mutex<Box<dyn DeckTransport>>>, kind: Kind) -> Self {
+        let shared: Shared = Arc::new((Mutex::new(State::default()), Condvar::new()));
+
+        let s = shared.clone();
+        let handle = std::thread::spawn(move || run(s, device, kind));
+
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Queue an image for upload to the provided device key index,
+    /// replacing any pending upload for the same key
+    pub(crate) fn queue(&self, key: u8, image: DeviceImage, chunk_size: usize) {
+        let mut state = lock(&self.shared);
+
+        state.pending.retain(|u| u.key != key);
+        state.pending.push(Upload {
+            key,
+            image,
+            chunk_size,
+        });
+
+        self.shared.1.notify_all();
+    }
+
+    /// Drop any pending uploads
+    pub(crate) fn discard(&self) {
+        lock(&self.shared).pending.clear();
+    }
+
+    /// Wait for all pending uploads to complete, returning the first error encountered
+    pub(crate) fn wait(&self) -> Result<(), Error> {
+        let mut state = lock(&self.shared);
+
+        while !state.pending.is_empty() || state.busy {
+            state = self.shared.1.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        match state.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for BackgroundWriter {
+    /// Pending uploads are completed before the thread exits
+    fn drop(&mut self) {
+        lock(&self.shared).shutdown = true;
+        self.shared.1.notify_all();
+
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+fn run(shared: Shared, device: Arc<Mutex<Box<dyn DeckTransport>>>, kind: Kind) {
+    let mut state = lock(&shared);
+
+    loop {
+        if state.pending.is_empty() {
+            if state.shutdown {
+                return;
+            }
+            state = shared.1.wait(state).unwrap_or_else(|e| e.into_inner());
+            continue;
+        }
+
+        let upload = state.pending.remove(0);
+        state.busy = true;
+        drop(state);
+
+        let res = {
+            let mut device = device.lock().unwrap_or_else(|e| e.into_inner());
+            let superseded = || lock(&shared).pending.iter().any(|u| u.key == upload.key);
+            upload_image(
+                &mut **device,
+                kind,
+                upload.chunk_size,
+                upload.key,
+                &upload.image.data,
+                &superseded,
+            )
+        };
+
+        state = lock(&shared);
+        state.busy = false;
+        if let Err(e) = res {
+            error!("background upload to key {} failed: {:?}", upload.key, e);
+            if state.error.is_none() {
+                state.error = Some(e);
+            }
+        }
+        shared.1.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::GatedTransport;
+    use crate::{Colour, MockTransport, StreamDeck};
+
+    #[test]
+    fn superseded_upload_abandoned() {
+        let kind = Kind::Xl;
+        let mock = MockTransport::new();
+        let (transport, started, resume) = GatedTransport::new(mock.clone());
+
+        let mut deck = StreamDeck::with_transport(transport, kind);
+        deck.set_upload_chunk_size(64).unwrap();
+        deck.set_background_uploads(true).unwrap();
+
+        // Start an upload, then replace it once the first packet is written
+        deck.set_button_rgb(3, &Colour::RED).unwrap();
+        started.recv().unwrap();
+        deck.set_button_rgb(3, &Colour::BLUE).unwrap();
+        resume.send(()).unwrap();
+        deck.flush().unwrap();
+
+        let packets = |c: &Colour| {
+            let mock = MockTransport::new();
+            let mut deck = StreamDeck::with_transport(mock.clone(), kind);
+            deck.set_upload_chunk_size(64).unwrap();
+            deck.set_button_rgb(3, c).unwrap();
+            mock.written_packets()
+        };
+        let (red, blue) = (packets(&Colour::RED), packets(&Colour::BLUE));
+        assert!(red.len() > 1);
+
+        let mut expected = vec![red[0].clone()];
+        expected.extend(blue);
+        assert_eq!(mock.written_packets(), expected);
+    }
+}
@@ -0,0 +1,58 @@
+//! Integration tests against a physically attached device
+//!
+//! These are only built with the `hardware-tests` feature, run with:
+//! `cargo test --features hardware-tests -- --test-threads=1`
+#![cfg(feature = "hardware-tests")]
+
+use hidapi::HidApi;
+use streamdeck::{Kind, StreamDeck};
+
+const ELGATO_VID: u16 = 0x0fd9;
+
+/// Connect to the first attached device with a recognised PID
+fn connect_first() -> StreamDeck {
+    let api = HidApi::new().expect("error initialising hidapi");
+
+    let info = api
+        .device_list()
+        .find(|d| d.vendor_id() == ELGATO_VID && Kind::from_pid(d.product_id()).is_some())
+        .expect("no supported device attached");
+
+    StreamDeck::connect_with_hid(&api, info.vendor_id(), info.product_id(), None)
+        .expect("error connecting to device")
+}
+
+#[test]
+fn serial_round_trip() {
+    let mut deck = connect_first();
+    let serial = deck.serial().expect("error fetching serial");
+    assert!(!serial.is_empty());
+
+    let kind = deck.kind();
+    drop(deck);
+
+    // Reconnect by serial and check we got the same device
+    let api = HidApi::new().expect("error initialising hidapi");
+    let pid = api
+        .device_list()
+        .find(|d| d.serial_number() == Some(serial.as_str()))
+        .map(|d| d.product_id())
+        .expect("device not found by serial");
+
+    let mut deck = StreamDeck::connect_with_hid(&api, ELGATO_VID, pid, Some(serial.clone()))
+        .expect("error reconnecting by serial");
+    assert_eq!(deck.kind(), kind);
+    assert_eq!(deck.serial().unwrap(), serial);
+}
+
+#[test]
+fn brightness_round_trip() {
+    let mut deck = connect_first();
+
+    for b in [0, 50, 100] {
+        deck.set_brightness(b).expect("error setting brightness");
+    }
+
+    deck.version()
+        .expect("error fetching version after brightness");
+}